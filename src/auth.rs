@@ -0,0 +1,199 @@
+use std::{collections::HashMap, path::Path};
+
+use reqwest::RequestBuilder;
+use resext::ctx;
+use url::Url;
+
+use crate::error::{Res, ResExt};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credentials {
+    Basic {
+        user: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+}
+
+impl Credentials {
+    /// Parses a `--auth` value in 'user:pass' form, a missing password is sent as empty
+    pub fn basic(auth: &str) -> Self {
+        match auth.split_once(':') {
+            Some((user, password)) => Self::Basic {
+                user: user.to_owned(),
+                password: Some(password.to_owned()),
+            },
+            None => Self::Basic {
+                user: auth.to_owned(),
+                password: None,
+            },
+        }
+    }
+
+    pub fn apply(&self, req: RequestBuilder) -> RequestBuilder {
+        match self {
+            Self::Basic { user, password } => req.basic_auth(user, password.as_ref()),
+            Self::Bearer(token) => req.bearer_auth(token),
+        }
+    }
+}
+
+/// Credentials keyed by the host they were configured for, URLs on any other host (including
+/// redirect targets) get none
+#[derive(Debug, Default)]
+pub struct Auth {
+    hosts: HashMap<String, Credentials>,
+}
+
+impl Auth {
+    /// Adds credentials for a host, credentials that were added first for the same host win
+    pub fn add(&mut self, host: &str, credentials: Credentials) {
+        self.hosts
+            .entry(host.to_ascii_lowercase())
+            .or_insert(credentials);
+    }
+
+    /// Adds the `machine` entries of a .netrc file, the `default` entry is ignored since it
+    /// would send the same credentials to every host
+    pub fn add_netrc(&mut self, content: &str) {
+        let mut tokens = NetrcTokens::new(content);
+        let mut machine: Option<String> = None;
+        let mut user = None;
+        let mut password = None;
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" | "default" => {
+                    if let Some(host) = machine.take()
+                        && let Some(user) = user.take()
+                    {
+                        self.add(&host, Credentials::Basic { user, password });
+                    }
+
+                    user = None;
+                    password = None;
+
+                    if token == "machine" {
+                        machine = tokens.next().map(str::to_owned);
+                    }
+                }
+
+                "login" => user = tokens.next().map(str::to_owned),
+                "password" => password = tokens.next().map(str::to_owned),
+                "account" => {
+                    tokens.next();
+                }
+                "macdef" => tokens.skip_macro(),
+
+                _ => {}
+            }
+        }
+
+        if let Some(host) = machine
+            && let Some(user) = user
+        {
+            self.add(&host, Credentials::Basic { user, password });
+        }
+    }
+
+    pub fn for_url(&self, url: &Url) -> Option<&Credentials> {
+        self.hosts.get(&url.host_str()?.to_ascii_lowercase())
+    }
+}
+
+/// Whitespace separated .netrc tokens, macro definitions run until the next empty line
+struct NetrcTokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> NetrcTokens<'a> {
+    fn new(content: &'a str) -> Self {
+        Self { rest: content }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (token, rest) = rest.split_at(end);
+        self.rest = rest;
+
+        Some(token).filter(|t| !t.is_empty())
+    }
+
+    fn skip_macro(&mut self) {
+        self.rest = match self.rest.find("\n\n") {
+            Some(i) => &self.rest[i..],
+            None => "",
+        };
+    }
+}
+
+/// Reads a .netrc file, `path` defaults to `$NETRC` or `~/.netrc`
+pub fn read_netrc(path: Option<&Path>) -> Res<String> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match std::env::var_os("NETRC") {
+            Some(path) => path.into(),
+            None => std::env::home_dir().unwrap_or_default().join(".netrc"),
+        },
+    };
+
+    std::fs::read_to_string(&path).context(ctx!(
+        "Failed to read .netrc file: {}",
+        path.to_string_lossy()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::{
+        auth::{Auth, Credentials},
+        error::{Res, ResExt},
+    };
+
+    #[test]
+    fn test_credentials_per_host() -> Res<()> {
+        let mut auth = Auth::default();
+        auth.add(
+            "wiki.example.com",
+            Credentials::Bearer(String::from("token")),
+        );
+        auth.add_netrc(
+            "machine wiki.example.com login ignored password ignored\n\
+             machine Staging.example.com\n  login admin\n  password s3cret\n\
+             macdef init\ncd /pub\n\n\
+             default login anonymous password guest\n",
+        );
+
+        let creds = |url: &str| -> Res<Option<Credentials>> {
+            let url = Url::parse(url).context("Failed to parse URL")?;
+            Ok(auth.for_url(&url).cloned())
+        };
+
+        assert_eq!(
+            creds("https://wiki.example.com/page")?,
+            Some(Credentials::Bearer(String::from("token")))
+        );
+        assert_eq!(
+            creds("http://staging.example.com:8080/")?,
+            Some(Credentials::Basic {
+                user: String::from("admin"),
+                password: Some(String::from("s3cret")),
+            })
+        );
+        assert_eq!(creds("https://example.com/")?, None);
+        assert_eq!(creds("https://cdn.wiki.example.com/")?, None);
+
+        assert_eq!(
+            Credentials::basic("user:pa:ss"),
+            Credentials::Basic {
+                user: String::from("user"),
+                password: Some(String::from("pa:ss")),
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
-use clap::ValueHint;
+use clap::{CommandFactory, FromArgMatches, ValueHint};
 use std::path::PathBuf;
 
+use crate::{
+    crawler::RelevanceMode, fetch::TrailingSlash, output::OutputFormat, repo::Strategy,
+    scope::RuleAction,
+};
+
 static LONG_ABT: &str = r#"
 crawn - A utility for web crawling and scraping
 
@@ -34,6 +39,35 @@ pub struct Args {
     #[arg(short, long, value_hint = ValueHint::FilePath, global = true)]
     pub log_file: Option<PathBuf>,
 
+    /// Output format: 'ndjson', 'json' (a single array), 'csv', 'tsv' or 'markdown' (a table)
+    /// (default: ndjson)
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Also write a record for every page that failed (with 'Error', 'ErrorKind', 'Status' and
+    /// 'Referrer' keys) to this NDJSON file, compressed like --output
+    #[arg(long, value_hint = ValueHint::FilePath, global = true)]
+    pub errors: Option<PathBuf>,
+
+    /// Comma-separated keys to write, in this order (e.g. 'URL,Status,Depth,Referrer,Title'),
+    /// keys are matched case-insensitively and --field names can be selected too (default: all)
+    #[arg(long = "fields", global = true, value_delimiter = ',')]
+    pub select_fields: Option<Vec<String>>,
+
+    /// Write output to a file instead of Stdout, compressed if it ends with '.gz' or '.zst', it is
+    /// written as '<path>.part' and renamed once complete, --resume continues in the next numbered
+    /// file
+    #[arg(short, long, value_hint = ValueHint::FilePath, global = true)]
+    pub output: Option<PathBuf>,
+
+    /// Start a new output file ('name-00001.ndjson', ...) after this many bytes (before compression)
+    #[arg(long, global = true, requires = "output")]
+    pub rotate_size: Option<u64>,
+
+    /// Start a new output file ('name-00001.ndjson', ...) after this many pages
+    #[arg(long, global = true, requires = "output")]
+    pub rotate_pages: Option<usize>,
+
     /// Include full HTML content in output (mutually exclusive with --include-text)
     #[arg(long, global = true, conflicts_with = "include_text")]
     pub include_content: bool,
@@ -42,6 +76,21 @@ pub struct Args {
     #[arg(long, global = true, conflicts_with = "include_content")]
     pub include_text: bool,
 
+    /// Include page metadata (description, robots, canonical, lang, OpenGraph, Twitter cards and
+    /// hreflang alternates) in output
+    #[arg(long, global = true)]
+    pub include_meta: bool,
+
+    /// Also archive the raw HTTP exchanges of fetched pages to a WARC 1.1 file (gzip per record),
+    /// existing files are appended to
+    #[arg(long, value_hint = ValueHint::FilePath, global = true)]
+    pub warc: Option<PathBuf>,
+
+    /// Size in bytes after which the WARC file rolls over to 'name-00001.warc.gz', ...
+    /// (default: 1073741824)
+    #[arg(long, global = true, requires = "warc")]
+    pub warc_max_size: Option<u64>,
+
     /// Maximum crawl depth (default: 4)
     #[arg(short, long, global = true)]
     pub max_depth: Option<u8>,
@@ -49,4 +98,229 @@ pub struct Args {
     /// Enable verbose logging - logs all HTTP requests instead of error warnings only
     #[arg(short, long, global = true)]
     pub verbose: bool,
+
+    /// Number of concurrent workers (default: 9, or 6 with --include-content/--include-text)
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub concurrency: Option<usize>,
+
+    /// Minimum delay between requests to the same host in milliseconds (default: 300)
+    #[arg(long, global = true)]
+    pub delay: Option<u64>,
+
+    /// Maximum random delay added to --delay in milliseconds (default: 300)
+    #[arg(long, global = true)]
+    pub jitter: Option<u64>,
+
+    /// Maximum number of concurrent requests to the same host (default: 1)
+    #[arg(long, global = true)]
+    pub max_per_host: Option<usize>,
+
+    /// Number of retries for timeouts, connection errors, 429 and 5xx responses (default: 2)
+    #[arg(long, global = true)]
+    pub retries: Option<u32>,
+
+    /// Base delay before the first retry in milliseconds, doubled on every retry unless the
+    /// server sends `Retry-After` (default: 500)
+    #[arg(long, global = true)]
+    pub retry_backoff: Option<u64>,
+
+    /// Extra output field in 'name=css[@attr]' form holding the text (or attribute) of the first
+    /// element matching the CSS selector, or null, can be repeated (names must be unique and
+    /// can't be a built-in key like URL or Title)
+    #[arg(long = "field", global = true)]
+    pub fields: Vec<String>,
+
+    /// Like --field but holds an array with every matching element
+    #[arg(long = "field-all", global = true)]
+    pub fields_all: Vec<String>,
+
+    /// Content types to parse, comma-separated, `type/*` matches a whole type (default:
+    /// text/html,application/xhtml+xml)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub content_types: Option<Vec<String>>,
+
+    /// Maximum response body size in bytes, larger responses are skipped (default: 10485760)
+    #[arg(long, global = true)]
+    pub max_body_size: Option<u64>,
+
+    /// User-Agent header sent with every request, its product token (e.g. `mybot` for
+    /// `MyBot/1.0`) is matched against robots.txt (default: crawn/<version>)
+    #[arg(short = 'A', long, global = true)]
+    pub user_agent: Option<String>,
+
+    /// Extra request header in 'Name: Value' form, can be repeated
+    #[arg(short = 'H', long = "header", global = true)]
+    pub headers: Vec<String>,
+
+    /// Cookies for the base URL's host in 'name=value; name2=value2' form, can be repeated
+    #[arg(long = "cookie", global = true)]
+    pub cookies: Vec<String>,
+
+    /// Load cookies from a Netscape cookies.txt file
+    #[arg(long, value_hint = ValueHint::FilePath, global = true)]
+    pub cookie_jar: Option<PathBuf>,
+
+    /// Basic auth credentials in 'user:pass' form, only sent to the base URL's host, takes priority
+    /// over --bearer-token
+    #[arg(long, global = true)]
+    pub auth: Option<String>,
+
+    /// Bearer token, only sent to the base URL's host
+    #[arg(
+        long,
+        global = true,
+        env = "CRAWN_BEARER_TOKEN",
+        hide_env_values = true
+    )]
+    pub bearer_token: Option<String>,
+
+    /// Read basic auth credentials per host from $NETRC or ~/.netrc
+    #[arg(long, global = true)]
+    pub netrc: bool,
+
+    /// Read basic auth credentials per host from this .netrc file
+    #[arg(long, value_hint = ValueHint::FilePath, global = true)]
+    pub netrc_file: Option<PathBuf>,
+
+    /// Proxy URL for every request (http://, https://, socks5:// or socks5h://), replaces the
+    /// HTTP_PROXY/HTTPS_PROXY/ALL_PROXY env vars
+    #[arg(long, global = true)]
+    pub proxy: Option<String>,
+
+    /// File with one proxy URL per line, requests rotate through them (and --proxy)
+    #[arg(long, value_hint = ValueHint::FilePath, global = true)]
+    pub proxy_list: Option<PathBuf>,
+
+    /// Comma-separated hosts (and their subdomains) to connect to directly, replaces the NO_PROXY
+    /// env var ('*' disables proxies)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub no_proxy: Option<Vec<String>>,
+
+    /// Domain or IP address to crawl, with an optional ':port' to only allow that port, can be
+    /// repeated (default: the base URL's host and explicit port)
+    #[arg(long = "allow-domain", global = true)]
+    pub allow_domains: Vec<String>,
+
+    /// Also crawl subdomains of the allowed domains
+    #[arg(long, global = true)]
+    pub include_subdomains: bool,
+
+    /// Only crawl URLs whose path starts with this prefix, can be repeated
+    #[arg(long = "path-prefix", global = true)]
+    pub path_prefixes: Vec<String>,
+
+    /// Crawl URLs matching this glob (or regex prefixed with 're:'), can be repeated, rules are
+    /// evaluated together with --exclude in the given order and the first match wins
+    #[arg(long, global = true)]
+    pub include: Vec<String>,
+
+    /// Skip URLs matching this glob (or regex prefixed with 're:'), can be repeated
+    #[arg(long, global = true)]
+    pub exclude: Vec<String>,
+
+    /// --include and --exclude rules in command line order
+    #[arg(skip)]
+    pub scope_rules: Vec<(RuleAction, String)>,
+
+    /// Keyword relevance filter: 'url' matches keywords of the URL path, 'anchor' also matches
+    /// the link text, 'off' crawls every in-scope URL (default: url)
+    #[arg(long, global = true, value_enum)]
+    pub relevance: Option<RelevanceMode>,
+
+    /// Comma-separated keywords added to the ones taken from the base URL's path
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub keywords: Vec<String>,
+
+    /// Only match --keywords, ignoring the keywords of the base URL's path
+    #[arg(long, global = true, requires = "keywords")]
+    pub no_seed_keywords: bool,
+
+    /// Minimum number of matching keywords for a URL to be crawled (default: 1)
+    #[arg(long, global = true)]
+    pub min_keyword_matches: Option<usize>,
+
+    /// Crawl order: 'bfs', 'dfs' or 'best-first' (highest keyword relevance and sitemap priority,
+    /// lowest depth first) (default: bfs)
+    #[arg(long, global = true, value_enum)]
+    pub strategy: Option<Strategy>,
+
+    /// Maximum number of pages to fetch, including failed ones (default: unlimited)
+    #[arg(long, global = true)]
+    pub max_pages: Option<usize>,
+
+    /// Crawl URLs even if the host's robots.txt disallows them
+    #[arg(long, global = true)]
+    pub ignore_robots: bool,
+
+    /// Comma-separated query params to remove from URLs, a trailing '*' matches any suffix ('' keeps
+    /// every param) (default: utm_*,gclid,fbclid,msclkid,dclid,yclid,mc_cid,mc_eid)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub strip_params: Option<Vec<String>>,
+
+    /// Trailing slash policy for URL paths: 'keep', 'strip' or 'add' (default: keep)
+    #[arg(long, global = true, value_enum)]
+    pub trailing_slash: Option<TrailingSlash>,
+
+    /// Remove 'index.html'/'index.htm' from the end of URL paths
+    #[arg(long, global = true)]
+    pub strip_index: bool,
+
+    /// Maximum number of differing SimHash bits for a page's text to count as a near-duplicate of
+    /// a page already written (default: 6, at most 63)
+    #[arg(long, global = true)]
+    pub near_duplicate_distance: Option<u32>,
+
+    /// Don't write near-duplicate pages or follow their links
+    #[arg(long, global = true)]
+    pub skip_near_duplicates: bool,
+
+    /// Treat pages as distinct even if their `<link rel="canonical">` points to the same URL
+    #[arg(long, global = true)]
+    pub ignore_canonical: bool,
+
+    /// Also seed the crawl with URLs from the site's sitemaps (robots.txt `Sitemap:` lines or /sitemap.xml)
+    #[arg(long, global = true)]
+    pub sitemaps: bool,
+
+    /// Directory for saving the crawl state (frontier and visited URLs) to disk after every batch
+    #[arg(long, value_hint = ValueHint::DirPath, global = true)]
+    pub state_dir: Option<PathBuf>,
+
+    /// Resume a previous crawl from the state saved in --state-dir
+    #[arg(long, global = true, requires = "state_dir")]
+    pub resume: bool,
+}
+
+impl Args {
+    /// Parses the args, keeping the command line order of --include and --exclude rules which
+    /// clap loses by collecting them into separate fields
+    pub fn parse_ordered() -> Self {
+        let matches = Self::command().get_matches();
+        let mut args = Self::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+        let mut rules = Vec::new();
+        for (id, action) in [
+            ("include", RuleAction::Include),
+            ("exclude", RuleAction::Exclude),
+        ] {
+            if let (Some(indices), Some(values)) =
+                (matches.indices_of(id), matches.get_many::<String>(id))
+            {
+                rules.extend(indices.zip(values).map(|(i, v)| (i, action, v.clone())));
+            }
+        }
+
+        rules.sort_by_key(|(i, _, _)| *i);
+        args.scope_rules = rules
+            .into_iter()
+            .map(|(_, action, value)| (action, value))
+            .collect();
+
+        args
+    }
 }
@@ -0,0 +1,136 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use resext::ctx;
+use url::Url;
+
+use crate::error::{Res, ResExt};
+
+/// Parses a Netscape cookies.txt file into `Set-Cookie` values and the URLs they were set for,
+/// skipping comments, malformed lines and expired cookies
+pub fn parse_cookie_jar(content: &str, now: SystemTime) -> Vec<(Url, String)> {
+    let mut cookies = Vec::new();
+
+    for line in content.lines() {
+        // curl marks HttpOnly cookies with a prefix on an otherwise commented-out line
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [
+            domain,
+            include_subdomains,
+            path,
+            secure,
+            expires,
+            name,
+            value,
+        ] = fields[..]
+        else {
+            continue;
+        };
+
+        let secure = secure.eq_ignore_ascii_case("TRUE");
+        let host = domain.trim_start_matches('.');
+
+        let url = match Url::parse(&format!(
+            "{}://{}{}",
+            if secure { "https" } else { "http" },
+            host,
+            path
+        )) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+
+        let mut cookie = format!("{}={}; Path={}", name, value, path);
+
+        // Without `Domain` the cookie is only sent to the exact host
+        if include_subdomains.eq_ignore_ascii_case("TRUE") {
+            cookie.push_str(&format!("; Domain={}", host));
+        }
+
+        if secure {
+            cookie.push_str("; Secure");
+        }
+
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+
+        // `0` marks a session cookie
+        match expires.parse::<u64>() {
+            Ok(0) => {}
+            Ok(secs) => {
+                let expires = UNIX_EPOCH + Duration::from_secs(secs);
+                if expires <= now {
+                    continue;
+                }
+
+                cookie.push_str(&format!("; Expires={}", httpdate::fmt_http_date(expires)));
+            }
+            Err(_) => continue,
+        }
+
+        cookies.push((url, cookie));
+    }
+
+    cookies
+}
+
+/// Reads cookies from a Netscape cookies.txt file
+pub fn read_cookie_jar(path: &std::path::Path) -> Res<Vec<(Url, String)>> {
+    let content = std::fs::read_to_string(path).context(ctx!(
+        "Failed to read cookie jar: {}",
+        path.to_string_lossy()
+    ))?;
+
+    Ok(parse_cookie_jar(&content, SystemTime::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::cookies::parse_cookie_jar;
+
+    #[test]
+    fn test_parse_cookie_jar() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+
+        let cookies = parse_cookie_jar(
+            "# Netscape HTTP Cookie File\n\
+             .example.com\tTRUE\t/\tFALSE\t0\tlang\ten\n\
+             #HttpOnly_example.com\tFALSE\t/app\tTRUE\t1900000000\tsession\tabc123\n\
+             example.com\tFALSE\t/\tFALSE\t1700000000\texpired\tyes\n\
+             malformed line\n",
+            now,
+        );
+
+        let cookies: Vec<(String, String)> = cookies
+            .into_iter()
+            .map(|(url, cookie)| (url.to_string(), cookie))
+            .collect();
+
+        assert_eq!(
+            cookies,
+            vec![
+                (
+                    String::from("http://example.com/"),
+                    String::from("lang=en; Path=/; Domain=example.com")
+                ),
+                (
+                    String::from("https://example.com/app"),
+                    String::from(
+                        "session=abc123; Path=/app; Secure; HttpOnly; Expires=Sun, 17 Mar 2030 17:46:40 GMT"
+                    )
+                ),
+            ]
+        );
+    }
+}
@@ -1,175 +1,901 @@
 use resext::ctx;
 use std::{
-    collections::HashSet,
-    sync::{Arc, LazyLock},
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicUsize, Ordering},
+    },
     time::{Duration, Instant},
 };
 
-use reqwest::{Client, Response};
+use reqwest::{
+    Client, Proxy, Response,
+    cookie::{CookieStore, Jar},
+    header::{ACCEPT, COOKIE, HeaderMap, HeaderName, HeaderValue, USER_AGENT},
+};
 use scraper::{Html, Selector};
-use tokio::{sync::Mutex, time::sleep};
+use tokio::sync::{Mutex, OnceCell};
 use url::Url;
 
 use crate::{
-    UrlRepo,
-    error::{Log, Res, ResExt},
+    FrontierEntry, UrlRepo,
+    auth::{Auth, Credentials, read_netrc},
+    cookies::read_cookie_jar,
+    error::{Log, Res, ResErr, ResExt},
     fetch::*,
+    limiter::{HostLimiter, HostPermit},
     match_option,
-    output::write_output,
+    output::{RECORD_KEYS, Record, write_output},
+    proxy::{ProxyConfig, redact_proxy},
+    robots::{Robots, user_agent_token},
+    scope::Scope,
+    simhash::{NearDuplicates, simhash},
+    warc::write_metadata,
 };
 
+/// A sent request, `permit` keeps the host's slot (`--max-per-host`, `--delay`) taken until it is
+/// released after the body has been read
+pub struct Sent {
+    pub res: Result<Response, reqwest::Error>,
+    /// Proxy (without credentials) the request was sent through
+    pub proxy: Option<String>,
+    /// When the request was sent, after waiting for the rate limit
+    pub sent_at: Instant,
+    pub permit: HostPermit,
+}
+
+/// robots.txt rules of a host, `expires` is set on the disallow-all fallback so a failed fetch is
+/// retried later instead of blocking the host for the whole crawl
+struct CachedRobots {
+    robots: Arc<Robots>,
+    expires: Option<Instant>,
+}
+
+/// How long the disallow-all fallback is used before robots.txt is fetched again
+const ROBOTS_FALLBACK_TTL: Duration = Duration::from_secs(60);
+
+/// robots.txt is only parsed up to this size, like RFC 9309 allows (500 KiB)
+const MAX_ROBOTS_SIZE: u64 = 500 * 1024;
+
+/// User-Agent sent when `--user-agent` isn't set
+pub const DEFAULT_USER_AGENT: &str = concat!("crawn/", env!("CARGO_PKG_VERSION"));
+
 pub struct CrawnClient {
     client: Client,
-    next_req: Mutex<Instant>,
+    limiter: HostLimiter,
+    robots: Mutex<HashMap<String, Arc<OnceCell<CachedRobots>>>>,
+    agent_token: String,
+    auth: Auth,
+    proxies: ProxyConfig,
+    proxy_clients: HashMap<String, Client>,
+    next_proxy: AtomicUsize,
+    /// Headers every request is sent with, for the WARC `request` records
+    headers: HeaderMap,
+    jar: Arc<Jar>,
 }
 
 impl CrawnClient {
-    pub fn new() -> Res<Self> {
-        Ok(Self {
-            client: Client::builder()
+    /// Builds the client from the CLI args, `--cookie` cookies are scoped to the base URL's host
+    pub fn new(base: &Url) -> Res<Self> {
+        let args = &*crate::ARGS;
+        let user_agent = args.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+
+        let mut headers = HeaderMap::new();
+        for header in &args.headers {
+            let (name, value) = parse_header(header)?;
+            headers.append(name, value);
+        }
+
+        // Cookies set by servers are stored in the jar too and sent for the rest of the crawl
+        let jar = Jar::default();
+
+        if let Some(path) = &args.cookie_jar {
+            for (url, cookie) in read_cookie_jar(path)? {
+                jar.add_cookie_str(&cookie, &url);
+            }
+        }
+
+        for cookie in args.cookies.iter().flat_map(|c| c.split(';')) {
+            if !cookie.trim().is_empty() {
+                jar.add_cookie_str(&format!("{}; Path=/", cookie.trim()), base);
+            }
+        }
+
+        // Credentials are attached per request instead of as default headers so they never
+        // reach other hosts, reqwest also drops them when a redirect leaves the host
+        let mut auth = Auth::default();
+
+        if let Some(host) = base.host_str() {
+            if let Some(credentials) = &args.auth {
+                auth.add(host, Credentials::basic(credentials));
+            } else if let Some(token) = &args.bearer_token {
+                auth.add(host, Credentials::Bearer(token.clone()));
+            }
+        }
+
+        if args.netrc || args.netrc_file.is_some() {
+            auth.add_netrc(&read_netrc(args.netrc_file.as_deref())?);
+        }
+
+        // Proxies are set per client, so there is one client per proxy sharing the cookie jar,
+        // env proxies are resolved by `ProxyConfig` to know which proxy served each page
+        let jar = Arc::new(jar);
+        let build = |proxy: Option<&str>| -> Res<Client> {
+            let builder = Client::builder()
                 .timeout(Duration::from_secs(10))
-                .build()
-                .context("Failed to build client")?,
+                .user_agent(user_agent)
+                .default_headers(headers.clone())
+                .cookie_provider(Arc::clone(&jar))
+                .no_proxy();
+
+            let builder = match proxy {
+                Some(proxy) => builder.proxy(
+                    Proxy::all(proxy)
+                        .context(ctx!("Invalid proxy URL: {}", redact_proxy(proxy)))?,
+                ),
+                None => builder,
+            };
+
+            builder.build().context("Failed to build client")
+        };
+
+        let proxies = ProxyConfig::from_args(args)?;
+
+        let mut request_headers = headers.clone();
+        request_headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(user_agent).map_err(|err| {
+                ResErr::from_args(ctx!("Invalid User-Agent: {}", user_agent), err.to_string())
+            })?,
+        );
+        request_headers
+            .entry(ACCEPT)
+            .or_insert(HeaderValue::from_static("*/*"));
 
-            next_req: Mutex::new(Instant::now()),
+        let mut proxy_clients = HashMap::new();
+
+        for proxy in proxies.all() {
+            if !proxy_clients.contains_key(proxy) {
+                proxy_clients.insert(proxy.clone(), build(Some(proxy))?);
+            }
+        }
+
+        Ok(Self {
+            client: build(None)?,
+
+            limiter: HostLimiter::new(
+                Duration::from_millis(args.delay.unwrap_or(300)),
+                Duration::from_millis(args.jitter.unwrap_or(300)),
+                args.max_per_host.unwrap_or(1),
+            ),
+            robots: Mutex::new(HashMap::new()),
+            agent_token: user_agent_token(user_agent),
+            auth,
+            proxies,
+            proxy_clients,
+            next_proxy: AtomicUsize::new(0),
+            headers: request_headers,
+            jar,
         })
     }
 
-    pub async fn get(&self, url: &str) -> Res<Response> {
-        let mut next_req = self.next_req.lock().await;
+    /// Fetches the URL's body through `send_with_retries`, failing on error statuses, bodies over
+    /// `max_size` bytes are truncated with a warning, the host's permit is held until the body
+    /// has been read
+    pub async fn get(&self, url: &str, max_size: u64) -> Res<Vec<u8>> {
+        let Received {
+            mut res, permit, ..
+        } = send_with_retries(self, url).await?;
+
+        // Streamed so a missing or wrong Content-Length can't make us buffer more than the limit
+        let mut body = Vec::new();
+        let mut truncated = false;
+        let read = loop {
+            match res.chunk().await {
+                Ok(Some(chunk)) => {
+                    let room = max_size.saturating_sub(body.len() as u64) as usize;
+                    body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+
+                    if chunk.len() > room {
+                        truncated = true;
+                        break Ok(());
+                    }
+                }
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+
+        permit.release().await;
+        read.context(ctx!("Failed to read body of URL: {}", url))?;
 
-        let now = Instant::now();
-        if now < *next_req {
-            sleep(*next_req - now).await;
+        if truncated {
+            Res::<()>::Err(ResErr::from_args(
+                ctx!("Truncated body of URL: {}", url),
+                format!("Body is larger than {} bytes", max_size),
+            ))
+            .log()
+            .await?;
         }
 
-        let res = self
-            .client
-            .get(url)
-            .send()
+        Ok(body)
+    }
+
+    /// Sends a rate-limited GET request, keeping the network error as is so the caller can
+    /// decide whether to retry it, the caller releases the permit once it's done with the
+    /// response (including its body)
+    pub async fn send(&self, url: &str) -> Res<Sent> {
+        let parsed = Url::parse(url).context(ctx!("Failed to parse URL: {}", url))?;
+        let host = parsed.origin().ascii_serialization();
+
+        let crawl_delay = self
+            .robots
+            .lock()
             .await
-            .context(ctx!("Failed to fetch URL: {}", url));
+            .get(&host)
+            .and_then(|cell| cell.get())
+            .and_then(|cached| cached.robots.crawl_delay);
+
+        let permit = self.limiter.acquire(&host, crawl_delay).await?;
+
+        let credentials = self.auth.for_url(&parsed);
+
+        let turn = self.next_proxy.fetch_add(1, Ordering::Relaxed);
+        let proxy = self.proxies.select(&parsed, turn);
+        let client = proxy
+            .and_then(|proxy| self.proxy_clients.get(proxy))
+            .unwrap_or(&self.client);
+        let proxy = proxy.map(redact_proxy);
 
-        *next_req = Instant::now() + Duration::from_millis(rand::random_range(300..601));
+        let mut req = client.get(parsed);
+        if let Some(credentials) = credentials {
+            req = credentials.apply(req);
+        }
+
+        let sent_at = Instant::now();
+        let res = req.send().await;
+
+        Ok(Sent {
+            res,
+            proxy,
+            sent_at,
+            permit,
+        })
+    }
+
+    /// Headers a request to the URL is sent with (as far as they can be known before reqwest adds
+    /// its own), credentials are left out so they don't end up in archives
+    pub fn request_headers(&self, url: &Url) -> HeaderMap {
+        let mut headers = self.headers.clone();
+
+        if let Some(cookies) = self.jar.cookies(url) {
+            headers.insert(COOKIE, cookies);
+        }
 
-        res
+        headers
     }
 
-    pub async fn timeout(&self, time: Duration) {
-        *self.next_req.lock().await = Instant::now() + time;
+    /// Returns the robots.txt rules for the URL's host, fetching them on first use
+    pub async fn robots(&self, url: &Url) -> Res<Arc<Robots>> {
+        let cell = {
+            let mut cache = self.robots.lock().await;
+            let cell = cache.entry(url.origin().ascii_serialization()).or_default();
+
+            if cell
+                .get()
+                .and_then(|cached| cached.expires)
+                .is_some_and(|expires| expires <= Instant::now())
+            {
+                *cell = Arc::default();
+            }
+
+            Arc::clone(cell)
+        };
+
+        let cached = cell
+            .get_or_try_init(async || -> Res<CachedRobots> {
+                Ok(match self.fetch_robots(url).await {
+                    Ok(robots) => CachedRobots {
+                        robots: Arc::new(robots),
+                        expires: None,
+                    },
+                    Err(err) => {
+                        let robots = Robots::disallow_all(err.to_string());
+                        Res::<()>::Err(err).log().await?;
+
+                        CachedRobots {
+                            robots: Arc::new(robots),
+                            expires: Some(Instant::now() + ROBOTS_FALLBACK_TTL),
+                        }
+                    }
+                })
+            })
+            .await?;
+
+        Ok(Arc::clone(&cached.robots))
     }
+
+    async fn fetch_robots(&self, url: &Url) -> Res<Robots> {
+        let robots_url = url
+            .join("/robots.txt")
+            .context(ctx!("Failed to resolve robots.txt URL for: {}", url))?;
+
+        let content = match self.get(robots_url.as_str(), MAX_ROBOTS_SIZE).await {
+            Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+            // Missing robots.txt (or any other 4xx) means there are no restrictions
+            Err(err) if err.status().is_some_and(|stat| (400..500).contains(&stat)) => {
+                return Ok(Robots::default());
+            }
+            Err(err) => {
+                return Err(err).context(ctx!(
+                    "Failed to fetch robots.txt, treating every URL on host as disallowed for {} s",
+                    ROBOTS_FALLBACK_TTL.as_secs()
+                ));
+            }
+        };
+
+        let robots = Robots::parse(&content, &self.agent_token);
+
+        if crate::ARGS.verbose {
+            format!(
+                "Fetched robots.txt from URL: {} (crawl-delay: {:?}, sitemaps: [{}])",
+                &robots_url,
+                robots.crawl_delay,
+                robots.sitemaps.join(", ")
+            )
+            .log()
+            .await?;
+        }
+
+        Ok(robots)
+    }
+
+    /// Pauses requests to the URL's host for `time`
+    pub async fn timeout(&self, url: &str, time: Duration) -> Res<()> {
+        self.limiter.pause(&host_key(url)?, time).await;
+
+        Ok(())
+    }
+}
+
+/// Parses a `--header` value in 'Name: Value' form
+fn parse_header(header: &str) -> Res<(HeaderName, HeaderValue)> {
+    let (name, value) = header.split_once(':').ok_or_else(|| {
+        ResErr::from_args(
+            ctx!("Failed to parse header: {}", header),
+            String::from("Expected 'Name: Value'"),
+        )
+    })?;
+
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|err| {
+        ResErr::from_args(ctx!("Invalid header name in: {}", header), err.to_string())
+    })?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|err| {
+        ResErr::from_args(ctx!("Invalid header value in: {}", header), err.to_string())
+    })?;
+
+    Ok((name, value))
+}
+
+/// Requests are limited per origin (scheme, host and port), same as robots.txt
+fn host_key(url: &str) -> Res<String> {
+    Ok(Url::parse(url)
+        .context(ctx!("Failed to parse URL: {}", url))?
+        .origin()
+        .ascii_serialization())
 }
 
 pub struct Selectors {
     pub anchor: Selector,
     pub title: Selector,
-    pub body: Option<Selector>,
+    pub body: Selector,
+    pub fields: Vec<Field>,
+    /// Not compiled with `--ignore-canonical`
+    pub canonical: Option<Selector>,
+    /// Only compiled with `--include-meta`
+    pub meta: Option<MetaSelectors>,
+}
+
+pub struct MetaSelectors {
+    pub meta: Selector,
+    pub link: Selector,
+    pub html: Selector,
+}
+
+impl MetaSelectors {
+    pub fn new() -> Res<Self> {
+        Ok(Self {
+            meta: Selector::parse("meta[content]")
+                .context("Failed to parse selector for HTML 'meta' tag")?,
+            link: Selector::parse("link[rel][href]")
+                .context("Failed to parse selector for HTML 'link' tag")?,
+            html: Selector::parse("html")
+                .context("Failed to parse selector for HTML 'html' tag")?,
+        })
+    }
+}
+
+/// User-defined output field from `--field`/`--field-all`
+pub struct Field {
+    pub name: String,
+    pub selector: Selector,
+    /// Attribute to extract instead of the element's text
+    pub attr: Option<String>,
+    /// Extract every match as an array instead of the first one
+    pub all: bool,
+}
+
+pub enum FieldValue {
+    First(Option<String>),
+    All(Vec<String>),
+}
+
+impl Field {
+    /// Parses a field spec in 'name=css[@attr]' form
+    pub fn parse(spec: &str, all: bool) -> Res<Self> {
+        let (name, css) = spec
+            .split_once('=')
+            .filter(|(name, css)| !name.trim().is_empty() && !css.trim().is_empty())
+            .ok_or_else(|| {
+                ResErr::from_args(
+                    ctx!("Failed to parse field: {}", spec),
+                    String::from("Expected 'name=css[@attr]'"),
+                )
+            })?;
+
+        if let Some(key) = RECORD_KEYS
+            .iter()
+            .find(|key| key.eq_ignore_ascii_case(name.trim()))
+        {
+            return Err(ResErr::from_args(
+                ctx!("Failed to parse field: {}", spec),
+                format!(
+                    "'{}' is a built-in key of every record, rename the field",
+                    key
+                ),
+            ));
+        }
+
+        // `@` can't appear in a CSS selector outside of quoted attribute values
+        let (css, attr) = match css.rsplit_once('@') {
+            Some((css, attr)) if !attr.contains([']', '"', '\'']) => (css, Some(attr.trim())),
+            _ => (css, None),
+        };
+
+        let selector = Selector::parse(css.trim()).map_err(|err| {
+            ResErr::from_args(
+                ctx!("Failed to parse selector for field: {}", spec),
+                err.to_string(),
+            )
+        })?;
+
+        Ok(Self {
+            name: name.trim().to_owned(),
+            selector,
+            attr: attr.map(str::to_owned),
+            all,
+        })
+    }
+
+    /// Parses the `--field` and `--field-all` specs, names must be unique (case-insensitively,
+    /// like `--fields`)
+    pub fn parse_all(first: &[String], all: &[String]) -> Res<Vec<Self>> {
+        let fields = first
+            .iter()
+            .map(|spec| Self::parse(spec, false))
+            .chain(all.iter().map(|spec| Self::parse(spec, true)))
+            .collect::<Res<Vec<Self>>>()?;
+
+        let mut names = HashSet::new();
+        if let Some(field) = fields
+            .iter()
+            .find(|field| !names.insert(field.name.to_lowercase()))
+        {
+            return Err(ResErr::from_args(
+                ctx!("Duplicate field name: {}", &field.name),
+                String::from("Every --field/--field-all name must be unique (case-insensitive)"),
+            ));
+        }
+
+        Ok(fields)
+    }
+}
+
+/// Canonical form of crawled URLs: links are normalized with the `UrlRules`, and pages that
+/// declare the same `rel=canonical` URL are written once, under the canonical URL, with the URLs
+/// they were found under as aliases
+pub struct Canonicals {
+    scope: Arc<Scope>,
+    url_rules: UrlRules,
+    /// Canonical URLs of aliases and pages that already ended, by what happened to their record
+    records: Mutex<HashMap<String, CanonicalRecord>>,
+}
+
+enum CanonicalRecord {
+    /// Not written yet, with the records of its aliases in case it never is
+    Pending(Vec<Record>),
+    Written,
+    /// The page ended without a record
+    Missing,
+}
+
+/// What happens to the record of an alias page, see `Canonicals::defer`
+pub enum Alias {
+    /// Kept until the canonical page either writes its record or ends without one
+    Pending,
+    /// The canonical page's record was already written
+    Duplicate,
+    /// The canonical page ended without a record, the alias is written under its own URL
+    Orphan(Box<Record>),
+}
+
+impl Canonicals {
+    /// Only canonical URLs inside the crawl scope are honoured
+    pub fn new(scope: Arc<Scope>, url_rules: UrlRules) -> Self {
+        Self {
+            scope,
+            url_rules,
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the URL to write the page's record under, or the canonical URL if the page is an
+    /// alias of one that was already seen (its record is handed to `defer`)
+    pub async fn resolve<R: UrlRepo>(
+        &self,
+        repo: &mut R,
+        url: &str,
+        canonical: Option<Url>,
+    ) -> Res<Result<String, String>> {
+        let canonical = match canonical.filter(|c| self.scope.check(c).is_ok()) {
+            Some(canonical) => normalize_url(canonical, &self.url_rules)?,
+            None => return Ok(Ok(url.to_owned())),
+        };
+
+        if canonical == url || repo.mark_seen(canonical.clone()).await? {
+            return Ok(Ok(canonical));
+        }
+
+        Ok(Err(canonical))
+    }
+
+    /// Keeps the record of an alias page until its canonical page ends, unless that already
+    /// happened
+    pub async fn defer(&self, canonical: &str, record: Record) -> Alias {
+        let mut records = self.records.lock().await;
+
+        match records
+            .entry(canonical.to_owned())
+            .or_insert_with(|| CanonicalRecord::Pending(Vec::new()))
+        {
+            CanonicalRecord::Pending(aliases) => {
+                aliases.push(record);
+                Alias::Pending
+            }
+            CanonicalRecord::Written => Alias::Duplicate,
+            CanonicalRecord::Missing => Alias::Orphan(Box::new(record)),
+        }
+    }
+
+    /// Marks the page's record as written, returns the URLs of the aliases that were waiting
+    /// for it
+    pub async fn written(&self, url: &str) -> Vec<String> {
+        match self
+            .records
+            .lock()
+            .await
+            .insert(url.to_owned(), CanonicalRecord::Written)
+        {
+            Some(CanonicalRecord::Pending(aliases)) => {
+                aliases.into_iter().map(|alias| alias.entry.url).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Called once the page is done, if it didn't write its record returns the records of the
+    /// aliases that were waiting for it
+    pub async fn finish(&self, url: &str) -> Vec<Record> {
+        let mut records = self.records.lock().await;
+
+        if let Some(CanonicalRecord::Written) = records.get(url) {
+            return Vec::new();
+        }
+
+        match records.insert(url.to_owned(), CanonicalRecord::Missing) {
+            Some(CanonicalRecord::Pending(aliases)) => aliases,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the records of every alias whose canonical page was never crawled
+    pub async fn finish_all(&self) -> Vec<Record> {
+        self.records
+            .lock()
+            .await
+            .values_mut()
+            .flat_map(|record| match record {
+                CanonicalRecord::Pending(aliases) => std::mem::take(aliases),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    /// Writes a page's record, listing the aliases that were waiting for it
+    pub async fn write(&self, mut record: Record) -> Res<()> {
+        record.aliases.extend(self.written(&record.entry.url).await);
+
+        write_output(record).await
+    }
+
+    /// Writes the records of aliases whose canonical page ended without one under their own URLs
+    pub async fn write_orphans(&self, records: Vec<Record>) -> Res<()> {
+        for record in records {
+            let url = record.entry.url.clone();
+
+            format!(
+                "Writing alias URL under its own URL since its canonical page has no record: {}",
+                &url
+            )
+            .log()
+            .await?;
+
+            self.write(record)
+                .await
+                .context(ctx!("Failed to write output entry for URL: {}", &url))?;
+        }
+
+        Ok(())
+    }
+
+    /// Normalizes a URL with the crawl's `UrlRules`
+    pub fn normalize(&self, url: Url) -> Res<String> {
+        normalize_url(url, &self.url_rules)
+    }
+
+    /// Normalizes a discovered link, `None` if it's out of scope so it never reaches the frontier
+    /// (or the state files)
+    pub fn link(&self, url: Url) -> Res<Option<String>> {
+        let link = self.normalize(url)?;
+        let parsed = Url::parse(&link).context(ctx!("Failed to parse URL: {}", &link))?;
+
+        Ok(self.scope.check(&parsed).is_ok().then_some(link))
+    }
 }
 
 pub async fn worker<R: UrlRepo>(
     repo: Arc<Mutex<R>>,
     selectors: Arc<Selectors>,
     client: Arc<CrawnClient>,
-    url: String,
+    canonicals: Arc<Canonicals>,
+    near_duplicates: Arc<NearDuplicates>,
+    entry: FrontierEntry,
     can_extract: bool,
 ) -> Res<()> {
     let args = &*crate::ARGS;
     let client = Arc::clone(&client);
+    let url = entry.url.clone();
 
-    let base = Url::parse(&url).context(ctx!("Failed to parse URL: {}", &url))?;
-
-    let content = fetch_url(&url, client).await?;
+    let FetchedPage {
+        content,
+        url: base,
+        proxy,
+        warc_id,
+        info,
+    } = match fetch_url(&url, client, &canonicals.scope).await? {
+        Some(page) => page,
+        None => return Ok(()),
+    };
 
     if args.verbose {
         format!("Fetched content from URL: {}", &url).log().await?;
     }
 
-    let (links, title, text, content) = {
-        let selectors = Arc::clone(&selectors);
-        let mut link_count = 0usize;
+    let selectors = Arc::clone(&selectors);
+    let record_entry = entry.clone();
+    let target = base.to_string();
 
-        let task = tokio::task::spawn_blocking(move || {
-            let doc = Html::parse_document(&content);
-            let links = if can_extract {
-                extract_links(&doc, Arc::new(base), &selectors.anchor)
-            } else {
-                Vec::new()
-            };
+    let task = tokio::task::spawn_blocking(move || {
+        let doc = Html::parse_document(&content);
+        let base = Arc::new(base);
+        let links = if can_extract {
+            extract_links(&doc, Arc::clone(&base), &selectors.anchor)
+        } else {
+            Vec::new()
+        };
+
+        let text = extract_text(&doc, &selectors.body);
+        let fingerprint = simhash(&text);
+        let canonical = selectors
+            .canonical
+            .as_ref()
+            .and_then(|link_selector| extract_canonical(&doc, link_selector, &base));
 
-            let text = selectors
-                .body
+        let record = Record {
+            entry: record_entry,
+            title: extract_title(&doc, &selectors.title),
+            links: 0,
+            text: args.include_text.then_some(text),
+            content: args.include_content.then_some(content),
+            proxy,
+            aliases: Vec::new(),
+            duplicate_of: None,
+            fields: extract_fields(&doc, &selectors.fields),
+            meta: selectors
+                .meta
                 .as_ref()
-                .map(|body_selector| extract_text(&doc, body_selector));
-            let title = extract_title(&doc, &selectors.title);
-
-            (
-                text,
-                title,
-                links,
-                if args.include_content {
-                    Some(content)
-                } else {
-                    None
-                },
-            )
-        });
+                .map(|meta_selectors| extract_meta(&doc, meta_selectors, &base)),
+            fetch: info,
+        };
+
+        (record, links, canonical, fingerprint)
+    });
 
-        let (text, title, links, content) = task
+    let (mut record, links, canonical, fingerprint) = task
+        .await
+        .context("Failed to extract links and text from HTML body concurrently")?;
+
+    if let Some(warc_id) = &warc_id {
+        write_metadata(&target, warc_id, &outlinks(&links))
             .await
-            .context("Failed to extract links and text from HTML body concurrently")?;
+            .context(ctx!("Failed to archive metadata of URL: {}", &url))?;
+    }
 
-        {
-            let temp = Arc::clone(&repo);
-            let mut rp = temp.lock().await;
+    let resolved = canonicals
+        .resolve(&mut *repo.lock().await, &url, canonical)
+        .await?;
 
+    if let (Ok(canonical), Some(fingerprint)) = (&resolved, fingerprint) {
+        record.duplicate_of = near_duplicates.check(canonical, fingerprint).await;
+    }
+
+    let checkpoint = {
+        let mut rp = repo.lock().await;
+
+        // Links of aliases are still followed since their canonical page may never be crawled,
+        // near-duplicates are only a dead end with --skip-near-duplicates
+        if !(args.skip_near_duplicates && record.duplicate_of.is_some()) {
             for link in links {
-                let link = match_option!(link.log().await?);
-                let link = match_option!(normalize_url(link).log().await?);
+                let (link, anchor) = match_option!(link.log().await?);
+                let link = match_option!(canonicals.link(link).log().await?.flatten());
 
-                match_option!(rp.add(link).await.log().await?);
+                match_option!(
+                    rp.add(entry.child(link).with_anchor(anchor))
+                        .await
+                        .log()
+                        .await?
+                );
 
-                link_count += 1;
+                record.links += 1;
             }
         }
 
-        (link_count, title, text, content)
+        rp.checkpoint()
     };
 
-    write_output(url, title, links, text, content)
+    checkpoint
+        .save()
+        .await
+        .context(ctx!("Failed to save crawl state after URL: {}", &url))?;
+
+    match resolved {
+        Ok(canonical) => {
+            if canonical != url {
+                record.aliases.push(url.clone());
+            }
+            record.entry.url = canonical;
+        }
+        Err(canonical) => {
+            match canonicals.defer(&canonical, record).await {
+                Alias::Pending => {}
+                Alias::Duplicate => {
+                    format!(
+                        "Skipped duplicate of canonical URL: {} (canonical: {})",
+                        &url, canonical
+                    )
+                    .log()
+                    .await?;
+                }
+                Alias::Orphan(record) => canonicals.write_orphans(vec![*record]).await?,
+            }
+
+            return Ok(());
+        }
+    }
+
+    if args.skip_near_duplicates
+        && let Some(original) = &record.duplicate_of
+    {
+        format!(
+            "Skipped near-duplicate URL: {} (near-duplicate of: {})",
+            &url, original
+        )
+        .log()
+        .await?;
+
+        return Ok(());
+    }
+
+    canonicals
+        .write(record)
         .await
         .context("Failed to write output entry for URL")?;
 
     Ok(())
 }
 
+/// Which keywords of a discovered URL are matched against the seed keywords
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum RelevanceMode {
+    /// Crawl every in-scope URL
+    Off,
+    /// Keywords from the URL path
+    Url,
+    /// Keywords from the URL path and the text of the link it was found through
+    Anchor,
+}
+
 static GENERICS: LazyLock<HashSet<&'static str>> =
     LazyLock::new(|| HashSet::from(["tutorial", "guide", "blog"]));
 
-pub fn should_crawl(
-    base_domain: Arc<String>,
-    base_keywords: Arc<HashSet<String>>,
-    other: &Url,
-) -> bool {
-    if let Some(other_domain) = other.domain() {
-        if other_domain != base_domain.as_str() {
-            return false;
+/// Keyword relevance filter, a URL is relevant if at least `min_matches` of its keywords are
+/// seed keywords or generic ones (tutorial, guide, blog)
+pub struct Relevance {
+    pub mode: RelevanceMode,
+    pub keywords: HashSet<String>,
+    pub min_matches: usize,
+}
+
+impl Relevance {
+    /// Returns the number of matching keywords for an irrelevant URL, an empty keyword set
+    /// turns filtering off since nothing could ever match
+    pub fn check(&self, url: &Url, anchor: Option<&str>) -> Result<(), usize> {
+        if self.mode == RelevanceMode::Off || self.keywords.is_empty() {
+            return Ok(());
         }
-    } else {
-        return false;
-    }
 
-    let other_keywords = get_keywords(other);
+        let anchor = anchor.filter(|_| self.mode == RelevanceMode::Anchor);
+        let match_count = self.matches(url, anchor);
+
+        if match_count >= self.min_matches {
+            Ok(())
+        } else {
+            Err(match_count)
+        }
+    }
 
-    let match_count = other_keywords
-        .iter()
-        .filter(|kw| base_keywords.contains(kw.as_str()) || GENERICS.contains(&kw.as_str()))
-        .count();
+    /// Score for best-first crawling: keyword matches of the URL and its link text, plus the
+    /// sitemap priority (0.5 if unknown), minus a penalty for every level of depth
+    pub fn score(&self, entry: &FrontierEntry) -> f32 {
+        let matches = Url::parse(&entry.url)
+            .map(|url| self.matches(&url, entry.anchor.as_deref()))
+            .unwrap_or_default();
 
-    if match_count >= 2 {
-        return true;
+        matches as f32 + entry.priority.unwrap_or(0.5) - 0.25 * entry.depth as f32
     }
 
-    true
+    fn matches(&self, url: &Url, anchor: Option<&str>) -> usize {
+        let mut other_keywords = get_keywords(url);
+
+        if let Some(anchor) = anchor {
+            other_keywords.extend(
+                anchor
+                    .to_lowercase()
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|s| {
+                        s.len() >= 3
+                            && !s.chars().all(|c| c.is_numeric())
+                            && !STOP_WORDS.contains(s)
+                    })
+                    .map(str::to_owned),
+            );
+        }
+
+        other_keywords
+            .iter()
+            .filter(|kw| self.keywords.contains(kw.as_str()) || GENERICS.contains(&kw.as_str()))
+            .count()
+    }
 }
 
 // common stop words
@@ -186,6 +912,11 @@ static STOP_WORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
         "by",
         "category",
         "catalogue",
+        "html",
+        "htm",
+        "php",
+        "asp",
+        "aspx",
     ])
 });
 
@@ -197,7 +928,7 @@ pub fn get_keywords(url: &Url) -> HashSet<String> {
 
     let path = url.path().to_lowercase();
 
-    path.split(['/', '-', '_'])
+    path.split(['/', '-', '_', '.'])
         .filter(|s| {
             !s.chars().all(|c| c.is_numeric())
                 && !s.is_empty()
@@ -210,19 +941,23 @@ pub fn get_keywords(url: &Url) -> HashSet<String> {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
+    use std::{collections::HashSet, sync::Arc};
 
     use url::Url;
 
     use crate::{
-        crawler::get_keywords,
+        FrontierEntry, InMemoryRepo, UrlRepo,
+        crawler::{Alias, Canonicals, Relevance, RelevanceMode, get_keywords},
         error::{Res, ResExt},
+        fetch::{FetchInfo, UrlRules},
+        output::Record,
+        scope::Scope,
     };
 
     #[test]
     fn test_keyword_extraction() -> Res<()> {
         let url = Url::parse(
-            "https://example.com/rust-programming-language/category/async/tokio/beginner_tutorial",
+            "https://example.com/rust-programming-language/category/async/tokio/beginner_tutorial.html",
         )
         .context("Failed to parse URL")?;
 
@@ -243,4 +978,181 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_relevance() -> Res<()> {
+        let mut relevance = Relevance {
+            mode: RelevanceMode::Url,
+            keywords: HashSet::from(["rust".to_string(), "async".to_string()]),
+            min_matches: 2,
+        };
+
+        let url = |url: &str| Url::parse(url).context("Failed to parse URL");
+
+        assert_eq!(
+            relevance.check(&url("https://example.com/rust/async")?, None),
+            Ok(())
+        );
+        assert_eq!(
+            relevance.check(&url("https://example.com/rust-guide")?, None),
+            Ok(())
+        );
+        assert_eq!(
+            relevance.check(&url("https://example.com/rust/pricing")?, None),
+            Err(1)
+        );
+        assert_eq!(
+            relevance.check(&url("https://example.com/p/123")?, Some("Async Rust")),
+            Err(0)
+        );
+
+        relevance.mode = RelevanceMode::Anchor;
+        assert_eq!(
+            relevance.check(&url("https://example.com/p/123")?, Some("Async Rust")),
+            Ok(())
+        );
+
+        relevance.mode = RelevanceMode::Off;
+        assert_eq!(
+            relevance.check(&url("https://example.com/pricing")?, None),
+            Ok(())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_canonical_aliases() -> Res<()> {
+        let scope = Scope::new(vec![String::from("example.com")], false, Vec::new(), &[])?;
+        let canonicals = Canonicals::new(Arc::new(scope), UrlRules::default());
+        let mut repo = InMemoryRepo::default();
+
+        let url = |url: &str| Url::parse(url).context("Failed to parse URL");
+        let page = "https://example.com/post";
+
+        let record = |url: &str| Record {
+            entry: FrontierEntry::from(String::from(url)),
+            title: String::new(),
+            links: 0,
+            text: None,
+            content: None,
+            proxy: None,
+            aliases: Vec::new(),
+            duplicate_of: None,
+            fields: Vec::new(),
+            meta: None,
+            fetch: FetchInfo::default(),
+        };
+        let urls = |records: Vec<Record>| -> Vec<String> {
+            records.into_iter().map(|r| r.entry.url).collect()
+        };
+
+        // The canonical was already queued, so the alias is reported on its record later
+        let alias = "https://example.com/post?utm_source=x";
+        repo.add(String::from(page).into()).await?;
+        assert_eq!(
+            canonicals
+                .resolve(&mut repo, alias, Some(url(page)?))
+                .await?,
+            Err(String::from(page))
+        );
+        assert!(matches!(
+            canonicals.defer(page, record(alias)).await,
+            Alias::Pending
+        ));
+        assert_eq!(
+            canonicals
+                .resolve(&mut repo, page, Some(url(page)?))
+                .await?,
+            Ok(String::from(page))
+        );
+        assert_eq!(canonicals.written(page).await, vec![String::from(alias)]);
+        assert!(canonicals.finish(page).await.is_empty());
+
+        // Aliases found after the canonical's record was written are only skipped
+        assert!(matches!(
+            canonicals
+                .defer(page, record("https://example.com/post?ref=y"))
+                .await,
+            Alias::Duplicate
+        ));
+
+        // The first alias seen is written under the canonical URL
+        assert_eq!(
+            canonicals
+                .resolve(
+                    &mut repo,
+                    "https://example.com/print/article",
+                    Some(url("https://example.com/article#top")?)
+                )
+                .await?,
+            Ok(String::from("https://example.com/article"))
+        );
+        assert_eq!(
+            canonicals
+                .resolve(
+                    &mut repo,
+                    "https://example.com/article?page=1",
+                    Some(url("https://example.com/article")?)
+                )
+                .await?,
+            Err(String::from("https://example.com/article"))
+        );
+
+        // Aliases of a canonical page that ends without a record are written under their own URL
+        let gone = "https://example.com/gone";
+        repo.add(String::from(gone).into()).await?;
+        assert!(matches!(
+            canonicals
+                .defer(gone, record("https://example.com/gone?a"))
+                .await,
+            Alias::Pending
+        ));
+        assert_eq!(
+            urls(canonicals.finish(gone).await),
+            vec![String::from("https://example.com/gone?a")]
+        );
+        assert!(matches!(
+            canonicals
+                .defer(gone, record("https://example.com/gone?b"))
+                .await,
+            Alias::Orphan(_)
+        ));
+
+        // Same for canonical pages that were never crawled
+        assert!(matches!(
+            canonicals
+                .defer(
+                    "https://example.com/deep",
+                    record("https://example.com/deep?a")
+                )
+                .await,
+            Alias::Pending
+        ));
+        assert_eq!(
+            urls(canonicals.finish_all().await),
+            vec![String::from("https://example.com/deep?a")]
+        );
+
+        // Out of scope links are never queued
+        assert_eq!(
+            canonicals.link(url("https://example.com/docs#intro")?)?,
+            Some(String::from("https://example.com/docs"))
+        );
+        assert_eq!(canonicals.link(url("https://other.com/docs")?)?, None);
+
+        // Out of scope canonicals are ignored
+        assert_eq!(
+            canonicals
+                .resolve(
+                    &mut repo,
+                    "https://example.com/syndicated",
+                    Some(url("https://other.com/original")?)
+                )
+                .await?,
+            Ok(String::from("https://example.com/syndicated"))
+        );
+
+        Ok(())
+    }
 }
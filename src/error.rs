@@ -20,10 +20,47 @@ pub enum CrawnError {
     NetworkError(reqwest::Error),
     UrlParseError(url::ParseError),
     ScrapeError(scraper::error::SelectorErrorKind<'static>),
+    XmlError(Box<quick_xml::Error>),
+    JsonError(Box<serde_json::Error>),
+    CsvError(Box<csv::Error>),
+    RegexError(Box<regex::Error>),
+    GlobError(Box<globset::Error>),
     ConcurrentTaskFailure(tokio::task::JoinError),
+    ConcurrencyError(tokio::sync::AcquireError),
     FmtError(String),
 }
 
+impl ResErr {
+    /// Category of the error for `--errors` records, network errors are split by what failed
+    pub fn kind(&self) -> &'static str {
+        match &self.source {
+            CrawnError::NetworkError(err) if err.status().is_some() => "HttpStatus",
+            CrawnError::NetworkError(err) if err.is_timeout() => "Timeout",
+            CrawnError::NetworkError(err) if err.is_connect() => "Connect",
+            CrawnError::NetworkError(err) if err.is_redirect() => "Redirect",
+            CrawnError::NetworkError(err) if err.is_body() || err.is_decode() => "Body",
+            CrawnError::NetworkError(_) => "Network",
+            CrawnError::IoError(_) => "Io",
+            CrawnError::UrlParseError(_) => "UrlParse",
+            CrawnError::ScrapeError(_) => "Scrape",
+            CrawnError::XmlError(_) | CrawnError::JsonError(_) | CrawnError::CsvError(_) => {
+                "Serialization"
+            }
+            CrawnError::RegexError(_) | CrawnError::GlobError(_) => "Config",
+            CrawnError::ConcurrentTaskFailure(_) | CrawnError::ConcurrencyError(_) => "Task",
+            CrawnError::FmtError(_) => "Other",
+        }
+    }
+
+    /// HTTP status of the response that failed, if the server responded
+    pub fn status(&self) -> Option<u16> {
+        match &self.source {
+            CrawnError::NetworkError(err) => err.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+}
+
 unsafe impl Send for CrawnError {}
 unsafe impl Sync for CrawnError {}
 unsafe impl Send for ResErr {}
@@ -96,6 +133,10 @@ pub const LOG_TIMESTAMP_FORMAT: &[time::format_description::BorrowedFormatItem]
     "[year]-[month padding:zero]-[day padding:zero] [hour]:[minute]:[second].[subsecond digits:3]"
 );
 
+/// UTC timestamps in output and archives
+pub const UTC_TIMESTAMP_FORMAT: &[time::format_description::BorrowedFormatItem] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]Z");
+
 impl<T> Log<T> for Res<T> {
     async fn log(self) -> Res<Option<T>> {
         match self {
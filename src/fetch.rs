@@ -1,47 +1,357 @@
 use resext::ctx;
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
-use reqwest::StatusCode;
+use reqwest::{
+    Response, StatusCode,
+    header::{CONTENT_TYPE, RETRY_AFTER},
+};
 use scraper::{Html, Selector};
-use url::Url;
+use tokio::time::sleep;
+use url::{Host, Url};
 
 use crate::{
-    crawler::CrawnClient,
-    error::{Res, ResErr, ResExt},
+    crawler::{CrawnClient, Field, FieldValue, MetaSelectors, Sent},
+    error::{Log, Res, ResErr, ResExt, UTC_TIMESTAMP_FORMAT},
+    limiter::HostPermit,
+    scope::Scope,
+    warc::{Exchange, write_exchange},
 };
 
-pub async fn fetch_url(url: &String, client: Arc<CrawnClient>) -> Res<String> {
-    let res = client.get(url).await?;
-    let stat = res.status();
+/// Upper bound for waiting on a server's `Retry-After` so one response can't stall the crawl
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(120);
+
+static DEFAULT_CONTENT_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+
+/// A fetched HTML page
+pub struct FetchedPage {
+    pub content: String,
+    /// Final URL after redirects, relative links are resolved against it
+    pub url: Url,
+    /// Proxy (without credentials) that served the page
+    pub proxy: Option<String>,
+    /// ID of the page's WARC `response` record, with `--warc`
+    pub warc_id: Option<String>,
+    pub info: FetchInfo,
+}
+
+/// Details of the response written to every record
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FetchInfo {
+    pub status: u16,
+    /// URL after redirects
+    pub final_url: String,
+    pub content_type: Option<String>,
+    /// Size of the body as received, in bytes
+    pub content_length: usize,
+    /// From sending the request to receiving the whole body
+    pub response_time: Duration,
+    /// When the body was received, UTC
+    pub fetched_at: String,
+}
+
+/// A response with a success status from `send_with_retries`, see `Sent`
+pub struct Received {
+    pub res: Response,
+    pub proxy: Option<String>,
+    pub sent_at: Instant,
+    pub permit: HostPermit,
+}
 
-    if !stat.is_success() {
-        if let StatusCode::TOO_MANY_REQUESTS = stat {
-            client.timeout(Duration::from_millis(2500)).await;
-            res.error_for_status_ref()
-                .context(ctx!("Failed to fetch URL: {}", url))
+/// Sends a GET request, retrying timeouts, connection errors, 429 and 5xx responses up to
+/// `--retries` times with exponential backoff (or the server's `Retry-After`), the caller
+/// releases the permit once it's done with the response
+pub async fn send_with_retries(client: &CrawnClient, url: &str) -> Res<Received> {
+    let args = &*crate::ARGS;
+    let retries = args.retries.unwrap_or(2);
+    let backoff = Duration::from_millis(args.retry_backoff.unwrap_or(500));
+
+    let mut attempt = 0u32;
+
+    loop {
+        attempt += 1;
+
+        let Sent {
+            res,
+            proxy,
+            sent_at,
+            permit,
+        } = client.send(url).await?;
+
+        let (err, stat, retry_after) = match res {
+            Ok(res) => match res.error_for_status_ref() {
+                Ok(_) => {
+                    return Ok(Received {
+                        res,
+                        proxy,
+                        sent_at,
+                        permit,
+                    });
+                }
+                Err(err) => (
+                    err,
+                    Some(res.status()),
+                    res.headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| parse_retry_after(v, SystemTime::now())),
+                ),
+            },
+            Err(err) => (err, None, None),
+        };
+
+        permit.release().await;
+
+        let is_retryable = match stat {
+            Some(stat) => stat == StatusCode::TOO_MANY_REQUESTS || stat.is_server_error(),
+            None => err.is_timeout() || err.is_connect() || err.is_request(),
+        };
+
+        let cause = match stat {
+            Some(stat) => format!("Server returned status code: {}", stat),
+            None => String::from("Request failed before receiving a response"),
+        };
+
+        if !is_retryable || attempt > retries {
+            return Err(err)
                 .context(ctx!(
-                    "Server returned {} response, status code: {}",
-                    "`TOO_MANY_REQUESTS`",
-                    "429"
+                    "Failed to fetch URL: {} after {} attempt(s)",
+                    url,
+                    attempt
                 ))
-                .context(
-                    "Will wait for 2.5 second timeout to avoid more bad responses and IP bans",
-                )?;
+                .context(cause.as_str());
+        }
+
+        let wait = match retry_after {
+            Some(wait) => wait.min(MAX_RETRY_WAIT),
+            None => backoff.saturating_mul(2u32.saturating_pow(attempt - 1)),
+        };
+
+        Err::<(), _>(err)
+            .context(ctx!(
+                "Attempt {}/{} to fetch URL: {} failed, retrying in {} ms",
+                attempt,
+                retries + 1,
+                url,
+                wait.as_millis()
+            ))
+            .context(cause.as_str())
+            .log()
+            .await?;
+
+        // Rate limiting and `Retry-After` apply to the whole host, other failures only delay
+        // this URL
+        if stat == Some(StatusCode::TOO_MANY_REQUESTS) || retry_after.is_some() {
+            client.timeout(url, wait).await?;
         } else {
-            res.error_for_status_ref()
-                .context(ctx!("Failed to fetch URL: {}", url))
-                .context(ctx!("Server returned status code: {}", stat))?;
+            sleep(wait).await;
+        }
+    }
+}
+
+/// Fetches the URL's HTML, returns `None` (after logging why) if the response isn't an allowed
+/// content type, exceeds `--max-body-size` or was redirected out of `scope` or to a URL disallowed
+/// by robots.txt
+pub async fn fetch_url(
+    url: &String,
+    client: Arc<CrawnClient>,
+    scope: &Scope,
+) -> Res<Option<FetchedPage>> {
+    let args = &*crate::ARGS;
+    let max_body_size = args.max_body_size.unwrap_or(10 * 1024 * 1024);
+
+    let Received {
+        mut res,
+        proxy,
+        sent_at,
+        permit,
+    } = send_with_retries(&client, url).await?;
+
+    let final_url = res.url().clone();
+
+    // Redirects are followed anywhere, so where they led has to pass the same checks as the URL
+    if final_url.as_str() != url {
+        let blocked = match scope.check(&final_url) {
+            Err(rule) => Some(format!("out of scope (rule: {})", rule)),
+            Ok(_)
+                if !args.ignore_robots
+                    && !client.robots(&final_url).await?.is_allowed(&final_url) =>
+            {
+                Some(String::from("disallowed by robots.txt"))
+            }
+            Ok(_) => None,
+        };
+
+        if let Some(reason) = blocked {
+            permit.release().await;
+
+            format!(
+                "Skipped URL: {} (redirected to: {}, which is {})",
+                url, final_url, reason
+            )
+            .log()
+            .await?;
+
+            return Ok(None);
         }
     }
-    let text = res
-        .text()
+
+    // The permit is held until the body has been read so `--max-per-host` and `--delay` cover
+    // the download too
+    let body = read_body(url, &mut res, max_body_size).await;
+    permit.release().await;
+
+    let Some(body) = body? else {
+        return Ok(None);
+    };
+
+    let info = FetchInfo {
+        status: res.status().as_u16(),
+        final_url: final_url.to_string(),
+        content_type: res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned),
+        content_length: body.len(),
+        response_time: sent_at.elapsed(),
+        fetched_at: time::OffsetDateTime::now_utc()
+            .format(&UTC_TIMESTAMP_FORMAT)
+            .map_err(|_| String::from("Format Failure"))
+            .context("Failed to format fetch timestamp")?,
+    };
+
+    let warc_id = write_exchange(Exchange {
+        url: &final_url,
+        request_headers: client.request_headers(&final_url),
+        version: res.version(),
+        status: res.status(),
+        headers: res.headers(),
+        body: &body,
+        remote_addr: res.remote_addr(),
+    })
+    .await
+    .context(ctx!("Failed to archive response of URL: {}", url))?;
+
+    Ok(Some(FetchedPage {
+        content: String::from_utf8_lossy(&body).into_owned(),
+        url: final_url,
+        proxy,
+        warc_id,
+        info,
+    }))
+}
+
+/// Reads the body of an allowed content type, returns `None` (after logging why) if it isn't one
+/// or the body exceeds `max_body_size`
+async fn read_body(url: &str, res: &mut Response, max_body_size: u64) -> Res<Option<Vec<u8>>> {
+    let args = &*crate::ARGS;
+
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+
+    let is_allowed = match &args.content_types {
+        Some(allowed) => is_allowed_content_type(content_type, allowed),
+        None => is_allowed_content_type(content_type, DEFAULT_CONTENT_TYPES),
+    };
+
+    if !is_allowed {
+        format!(
+            "Skipped resource with content type: {} at URL: {}",
+            content_type.unwrap_or_default(),
+            url
+        )
+        .log()
+        .await?;
+
+        return Ok(None);
+    }
+
+    if res.content_length().is_some_and(|len| len > max_body_size) {
+        format!(
+            "Skipped resource larger than {} bytes (Content-Length: {}) at URL: {}",
+            max_body_size,
+            res.content_length().unwrap_or_default(),
+            url
+        )
+        .log()
+        .await?;
+
+        return Ok(None);
+    }
+
+    // Streamed so a missing or wrong Content-Length can't make us download more than the limit
+    let mut body = Vec::new();
+    while let Some(chunk) = res
+        .chunk()
         .await
-        .context(ctx!("Failed to fetch HTML (content) from URL: {}", url))?;
+        .context(ctx!("Failed to fetch HTML (content) from URL: {}", url))?
+    {
+        if (body.len() + chunk.len()) as u64 > max_body_size {
+            format!(
+                "Skipped resource larger than {} bytes at URL: {}",
+                max_body_size, url
+            )
+            .log()
+            .await?;
+
+            return Ok(None);
+        }
+
+        body.extend_from_slice(&chunk);
+    }
 
-    Ok(text)
+    Ok(Some(body))
 }
 
-pub fn extract_links(document: &Html, base: Arc<Url>, anchor_selector: &Selector) -> Vec<Res<Url>> {
+/// Checks the MIME type of a `Content-Type` header against an allow-list, where `type/*` allows
+/// every subtype, responses without a `Content-Type` are allowed since many servers omit it
+pub fn is_allowed_content_type<S: AsRef<str>>(content_type: Option<&str>, allowed: &[S]) -> bool {
+    let content_type = match content_type {
+        Some(content_type) => content_type,
+        None => return true,
+    };
+
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    allowed.iter().any(|allowed| {
+        let allowed = allowed.as_ref().trim().to_lowercase();
+
+        match allowed.strip_suffix("/*") {
+            Some(main_type) => mime.split('/').next() == Some(main_type),
+            None => mime == allowed,
+        }
+    })
+}
+
+/// Parses a `Retry-After` header value, either delay-seconds or an HTTP-date
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()
+            .map(|date| date.duration_since(now).unwrap_or_default()),
+    }
+}
+
+/// Extracts the links of the document along with their (whitespace-collapsed) anchor text
+pub fn extract_links(
+    document: &Html,
+    base: Arc<Url>,
+    anchor_selector: &Selector,
+) -> Vec<Res<(Url, String)>> {
     document
         .select(anchor_selector)
         .map(|anchor| {
@@ -52,11 +362,142 @@ pub fn extract_links(document: &Html, base: Arc<Url>, anchor_selector: &Selector
                 )
             })?;
 
-            base.join(href)
-                .context(ctx!("Failed to resolve relative URL: {}", href))
+            let url = base
+                .join(href)
+                .context(ctx!("Failed to resolve relative URL: {}", href))?;
+            let text = anchor.text().collect::<String>();
+
+            Ok((
+                url,
+                text.split_whitespace().collect::<Vec<&str>>().join(" "),
+            ))
+        })
+        .collect()
+}
+
+/// Unique URLs of the extracted links (in document order) for the WARC `metadata` record
+pub fn outlinks(links: &[Res<(Url, String)>]) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    links
+        .iter()
+        .filter_map(|link| link.as_ref().ok())
+        .map(|(url, _)| url.to_string())
+        .filter(|url| seen.insert(url.clone()))
+        .collect()
+}
+
+/// Extracts the `--field`/`--field-all` values, the element's text is whitespace-collapsed
+pub fn extract_fields(document: &Html, fields: &[Field]) -> Vec<(String, FieldValue)> {
+    fields
+        .iter()
+        .map(|field| {
+            let mut values = document.select(&field.selector).filter_map(|el| {
+                match &field.attr {
+                    Some(attr) => el.attr(attr).map(str::to_owned),
+                    None => Some(el.text().collect::<String>()),
+                }
+                .map(|v| v.split_whitespace().collect::<Vec<&str>>().join(" "))
+            });
+
+            let value = if field.all {
+                FieldValue::All(values.collect())
+            } else {
+                FieldValue::First(values.next())
+            };
+
+            (field.name.clone(), value)
         })
         .collect()
 }
+
+/// Page metadata included with `--include-meta`, the first value of a repeated tag wins
+#[derive(Debug, Default, PartialEq)]
+pub struct PageMeta {
+    pub description: Option<String>,
+    pub robots: Option<String>,
+    /// `<link rel="canonical">` resolved against the page URL
+    pub canonical: Option<String>,
+    pub lang: Option<String>,
+    /// `og:*` properties without the prefix
+    pub open_graph: Vec<(String, String)>,
+    /// `twitter:*` properties without the prefix
+    pub twitter: Vec<(String, String)>,
+    /// `<link rel="alternate" hreflang>` languages and their resolved URLs
+    pub hreflang: Vec<(String, String)>,
+}
+
+pub fn extract_meta(document: &Html, selectors: &MetaSelectors, base: &Url) -> PageMeta {
+    let mut meta = PageMeta {
+        lang: document
+            .select(&selectors.html)
+            .next()
+            .and_then(|html| html.attr("lang"))
+            .map(|lang| lang.trim().to_owned())
+            .filter(|lang| !lang.is_empty()),
+        ..Default::default()
+    };
+
+    let push = |map: &mut Vec<(String, String)>, key: &str, value: String| {
+        if !map.iter().any(|(k, _)| k == key) {
+            map.push((key.to_owned(), value));
+        }
+    };
+
+    for el in document.select(&selectors.meta) {
+        let Some(content) = el.attr("content") else {
+            continue;
+        };
+        let content = content.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+        // OpenGraph uses `property` but plenty of sites put it (and Twitter cards) in `name`
+        let key = el
+            .attr("property")
+            .or_else(|| el.attr("name"))
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        if let Some(key) = key.strip_prefix("og:") {
+            push(&mut meta.open_graph, key, content);
+        } else if let Some(key) = key.strip_prefix("twitter:") {
+            push(&mut meta.twitter, key, content);
+        } else if key == "description" {
+            meta.description.get_or_insert(content);
+        } else if key == "robots" {
+            meta.robots.get_or_insert(content);
+        }
+    }
+
+    meta.canonical = extract_canonical(document, &selectors.link, base).map(String::from);
+
+    for el in document.select(&selectors.link) {
+        if has_rel(el.attr("rel"), "alternate")
+            && let Some(lang) = el.attr("hreflang")
+            && let Some(href) = el.attr("href").and_then(|href| base.join(href.trim()).ok())
+        {
+            push(&mut meta.hreflang, lang.trim(), href.to_string());
+        }
+    }
+
+    meta
+}
+
+/// Extracts the first `<link rel="canonical">` resolved against the page URL
+pub fn extract_canonical(document: &Html, link_selector: &Selector, base: &Url) -> Option<Url> {
+    document
+        .select(link_selector)
+        .filter(|el| has_rel(el.attr("rel"), "canonical"))
+        .find_map(|el| base.join(el.attr("href")?.trim()).ok())
+}
+
+/// `rel` holds a space-separated, case-insensitive list of link types
+fn has_rel(rel: Option<&str>, link_type: &str) -> bool {
+    rel.unwrap_or_default()
+        .split_whitespace()
+        .any(|r| r.eq_ignore_ascii_case(link_type))
+}
+
 pub fn extract_text(document: &Html, body_selector: &Selector) -> String {
     if let Some(body) = document.select(body_selector).next() {
         body.text()
@@ -77,31 +518,192 @@ pub fn extract_title(document: &Html, title_selector: &Selector) -> String {
     }
 }
 
-pub fn normalize_url(mut url: Url) -> Res<String> {
-    if let Some(domain) = url.domain() {
-        let res = url.set_host(Some(&domain.to_lowercase()));
-        res.context("Failed to set host domain for URL")?;
-    } else {
-        return Err(url::ParseError::EmptyHost).context(
-            "Failed to normalize host domain for URL as it does not contain a valid host domain",
-        );
+/// Tracking params stripped when `--strip-params` isn't set
+const DEFAULT_STRIP_PARAMS: [&str; 8] = [
+    "utm_*", "gclid", "fbclid", "msclkid", "dclid", "yclid", "mc_cid", "mc_eid",
+];
+
+/// What to do with a trailing slash on URL paths, the root path always keeps it
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum TrailingSlash {
+    /// Leave paths as they were linked
+    #[default]
+    Keep,
+    /// Remove the trailing slash
+    Strip,
+    /// Add a trailing slash unless the last segment looks like a file (has an extension)
+    Add,
+}
+
+/// Canonicalization rules applied by `normalize_url` on top of the URL parser's own (lowercase
+/// scheme, default ports removed, `.`/`..` segments resolved)
+#[derive(Clone, Debug, PartialEq)]
+pub struct UrlRules {
+    /// Query params to remove, a trailing `*` matches any suffix
+    pub strip_params: Vec<String>,
+    pub trailing_slash: TrailingSlash,
+    /// Remove `index.html`/`index.htm` from the end of paths
+    pub strip_index: bool,
+}
+
+impl Default for UrlRules {
+    fn default() -> Self {
+        Self {
+            strip_params: DEFAULT_STRIP_PARAMS.map(String::from).to_vec(),
+            trailing_slash: TrailingSlash::Keep,
+            strip_index: false,
+        }
+    }
+}
+
+impl UrlRules {
+    pub fn from_args(args: &crate::cli::Args) -> Self {
+        let mut rules = Self {
+            trailing_slash: args.trailing_slash.unwrap_or_default(),
+            strip_index: args.strip_index,
+            ..Default::default()
+        };
+
+        if let Some(params) = &args.strip_params {
+            rules.strip_params = params
+                .iter()
+                .map(|p| p.trim().to_owned())
+                .filter(|p| !p.is_empty())
+                .collect();
+        }
+
+        rules
+    }
+
+    fn is_stripped(&self, param: &str) -> bool {
+        let param = param.to_ascii_lowercase();
+
+        self.strip_params
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => param.starts_with(&prefix.to_ascii_lowercase()),
+                None => param.eq_ignore_ascii_case(pattern),
+            })
+    }
+}
+
+/// Normalizes a URL so that different spellings of the same page compare equal: lowercase host,
+/// no fragment, unreserved percent-escapes decoded (others uppercased), tracking params removed,
+/// query params sorted by name and the `rules` path policies applied
+pub fn normalize_url(mut url: Url, rules: &UrlRules) -> Res<String> {
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.to_lowercase();
+            let res = url.set_host(Some(&domain));
+            res.context("Failed to set host domain for URL")?;
+        }
+        // IP addresses are already serialized in their canonical form
+        Some(_) => {}
+        None => {
+            return Err(url::ParseError::EmptyHost)
+                .context("Failed to normalize host for URL as it does not contain a host");
+        }
     }
 
     url.set_fragment(None);
 
+    let mut path = normalize_escapes(url.path());
+
+    if rules.strip_index
+        && let Some((dir, last)) = path.rsplit_once('/')
+        && (last.eq_ignore_ascii_case("index.html") || last.eq_ignore_ascii_case("index.htm"))
+    {
+        path.truncate(dir.len() + 1);
+    }
+
+    match rules.trailing_slash {
+        TrailingSlash::Keep => {}
+        TrailingSlash::Strip => {
+            let len = path.trim_end_matches('/').len().max(1);
+            path.truncate(len);
+        }
+        TrailingSlash::Add => {
+            let last = path.rsplit('/').next().unwrap_or_default();
+
+            if !last.is_empty() && !last.contains('.') {
+                path.push('/');
+            }
+        }
+    }
+
+    url.set_path(&path);
+
+    if let Some(query) = url.query() {
+        let mut params: Vec<String> = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(normalize_escapes)
+            .filter(|param| !rules.is_stripped(param.split('=').next().unwrap_or_default()))
+            .collect();
+
+        // Stable, so repeated params keep their relative order
+        params.sort_by(|a, b| {
+            let key = |param: &String| param.split('=').next().unwrap_or_default().to_owned();
+            key(a).cmp(&key(b))
+        });
+
+        if params.is_empty() {
+            url.set_query(None);
+        } else {
+            url.set_query(Some(&params.join("&")));
+        }
+    }
+
     Ok(url.to_string())
 }
 
+/// Decodes percent-escapes of unreserved characters (RFC 3986) and uppercases the rest
+fn normalize_escapes(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match hex {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                out.push(byte as char);
+                i += 3;
+            }
+            Some(_) => {
+                out.push_str(&s[i..i + 3].to_ascii_uppercase());
+                i += 3;
+            }
+            None => {
+                let ch = s[i..].chars().next().unwrap_or_default();
+                out.push(ch);
+                i += ch.len_utf8();
+            }
+        }
+    }
+
+    out
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
     use scraper::{Html, Selector};
     use url::Url;
 
     use crate::{
+        crawler::{Field, FieldValue, MetaSelectors},
         error::{Res, ResExt},
-        fetch::{extract_links, extract_text, extract_title, normalize_url},
+        fetch::{
+            PageMeta, TrailingSlash, UrlRules, extract_fields, extract_links, extract_meta,
+            extract_text, extract_title, is_allowed_content_type, normalize_url, parse_retry_after,
+        },
     };
 
     #[test]
@@ -109,10 +711,69 @@ mod tests {
         let url = url::Url::parse("http://ExAmPlE.com/index.html#section3")
             .context("Failed to parse URL for testing")?;
 
-        let normalized = normalize_url(url).context("Failed to normalize URL")?;
+        let normalized =
+            normalize_url(url, &UrlRules::default()).context("Failed to normalize URL")?;
 
         assert_eq!(normalized, "http://example.com/index.html");
 
+        let normalize = |url: &str, rules: &UrlRules| -> Res<String> {
+            normalize_url(Url::parse(url).context("Failed to parse URL")?, rules)
+        };
+
+        assert_eq!(
+            normalize(
+                "https://example.com:443/a/./b/../c%7e%2fd?b=2&utm_source=feed&a=1&a=0&GCLID=x",
+                &UrlRules::default()
+            )?,
+            "https://example.com/a/c~%2Fd?a=1&a=0&b=2"
+        );
+        assert_eq!(
+            normalize("http://example.com/?utm_campaign=x", &UrlRules::default())?,
+            "http://example.com/"
+        );
+
+        let strip = UrlRules {
+            strip_params: vec![String::from("ref")],
+            trailing_slash: TrailingSlash::Strip,
+            strip_index: true,
+        };
+
+        assert_eq!(
+            normalize(
+                "http://example.com/docs/index.html?ref=nav&utm_id=1",
+                &strip
+            )?,
+            "http://example.com/docs?utm_id=1"
+        );
+        assert_eq!(
+            normalize("http://example.com/index.htm", &strip)?,
+            "http://example.com/"
+        );
+
+        assert_eq!(
+            normalize("http://127.0.0.1:8080/a#top", &UrlRules::default())?,
+            "http://127.0.0.1:8080/a"
+        );
+        assert_eq!(
+            normalize("http://[0:0::1]:3000/", &UrlRules::default())?,
+            "http://[::1]:3000/"
+        );
+        assert!(normalize("data:text/html,<p>hi</p>", &UrlRules::default()).is_err());
+
+        let add = UrlRules {
+            trailing_slash: TrailingSlash::Add,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            normalize("http://example.com/docs", &add)?,
+            "http://example.com/docs/"
+        );
+        assert_eq!(
+            normalize("http://example.com/docs/guide.html", &add)?,
+            "http://example.com/docs/guide.html"
+        );
+
         Ok(())
     }
 
@@ -167,7 +828,9 @@ mod tests {
 <html>
   <body>
     <a href="path/to/page/index.html">link</a>
-    <a href="/path/to/another/page/index.html">link</a>
+    <a href="/path/to/another/page/index.html">
+      another <b>link</b>
+    </a>
   </body>
 </html>
             "#,
@@ -185,13 +848,148 @@ mod tests {
             links
                 .iter()
                 .map(move |link| link.as_ref().unwrap().clone())
-                .collect::<Vec<Url>>(),
+                .collect::<Vec<(Url, String)>>(),
             vec![
-                Url::parse("https://example.com/category/path/to/page/index.html").unwrap(),
-                Url::parse("https://example.com/path/to/another/page/index.html").unwrap()
+                (
+                    Url::parse("https://example.com/category/path/to/page/index.html").unwrap(),
+                    String::from("link")
+                ),
+                (
+                    Url::parse("https://example.com/path/to/another/page/index.html").unwrap(),
+                    String::from("another link")
+                )
             ]
         );
 
         Ok(())
     }
+
+    #[test]
+    fn test_extract_fields() -> Res<()> {
+        let document = Html::parse_document(
+            r#"
+<html>
+  <body>
+    <span class="price"> $19<sup>.99</sup> </span>
+    <p class="author">By <a href="/ada">Ada</a>
+      Lovelace</p>
+    <nav><a href="/docs">Docs</a> <a href="/docs/async">Async</a></nav>
+  </body>
+</html>
+            "#,
+        );
+
+        let fields = [
+            Field::parse("price=.price", false)?,
+            Field::parse("author=.author", false)?,
+            Field::parse("crumbs=nav a[href^='/docs']@href", true)?,
+            Field::parse("rating=.rating", false)?,
+        ];
+
+        let values = extract_fields(&document, &fields);
+
+        assert!(matches!(
+            &values[0],
+            (name, FieldValue::First(Some(v))) if name == "price" && v == "$19.99"
+        ));
+        assert!(matches!(
+            &values[1],
+            (_, FieldValue::First(Some(v))) if v == "By Ada Lovelace"
+        ));
+        assert!(matches!(
+            &values[2],
+            (_, FieldValue::All(v)) if v == &["/docs", "/docs/async"]
+        ));
+        assert!(matches!(&values[3], (_, FieldValue::First(None))));
+
+        assert!(Field::parse("no-selector=", false).is_err());
+        assert!(Field::parse("status=.status", false).is_err());
+        assert!(
+            Field::parse_all(
+                &[String::from("price=.price")],
+                &[String::from("Price=.prices li")]
+            )
+            .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_meta() -> Res<()> {
+        let document = Html::parse_document(
+            r#"
+<html lang="en-US">
+  <head>
+    <meta name="description" content=" Async   Rust guide ">
+    <meta name="description" content="Ignored duplicate">
+    <meta name="robots" content="noindex, follow">
+    <meta property="og:title" content="Async Rust">
+    <meta name="og:type" content="article">
+    <meta name="twitter:card" content="summary">
+    <link rel="canonical" href="/docs/async">
+    <link rel="alternate" hreflang="de" href="https://example.de/docs/async">
+    <link rel="stylesheet" href="/style.css">
+  </head>
+</html>
+            "#,
+        );
+
+        let base = Url::parse("https://example.com/docs/async?ref=nav")
+            .context("Failed to parse URL for testing")?;
+        let meta = extract_meta(&document, &MetaSelectors::new()?, &base);
+
+        let pair = |k: &str, v: &str| (String::from(k), String::from(v));
+
+        assert_eq!(
+            meta,
+            PageMeta {
+                description: Some(String::from("Async Rust guide")),
+                robots: Some(String::from("noindex, follow")),
+                canonical: Some(String::from("https://example.com/docs/async")),
+                lang: Some(String::from("en-US")),
+                open_graph: vec![pair("title", "Async Rust"), pair("type", "article")],
+                twitter: vec![pair("card", "summary")],
+                hreflang: vec![pair("de", "https://example.de/docs/async")],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2026 07:28:00 GMT").unwrap();
+
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2026 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2026 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_content_type_allow_list() {
+        let allowed = ["text/html", "application/*"];
+
+        assert!(is_allowed_content_type(
+            Some("Text/HTML; charset=utf-8"),
+            &allowed
+        ));
+        assert!(is_allowed_content_type(
+            Some("application/xhtml+xml"),
+            &allowed
+        ));
+        assert!(is_allowed_content_type(None, &allowed));
+        assert!(!is_allowed_content_type(Some("image/png"), &allowed));
+        assert!(!is_allowed_content_type(Some("text/plain"), &allowed));
+    }
 }
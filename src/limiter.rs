@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    time::sleep,
+};
+
+use crate::error::{Res, ResExt};
+
+struct HostState {
+    next_req: Mutex<Instant>,
+    permits: Arc<Semaphore>,
+}
+
+/// Per-host politeness: requests to the same host are spaced by `delay` plus a random `jitter`
+/// (or the host's `Crawl-delay` if it is longer) with at most `max_per_host` in flight, while
+/// requests to different hosts never wait on each other
+pub struct HostLimiter {
+    hosts: Mutex<HashMap<String, Arc<HostState>>>,
+    delay: Duration,
+    jitter: Duration,
+    max_per_host: usize,
+}
+
+/// Held while a request to a host is in flight
+pub struct HostPermit {
+    state: Arc<HostState>,
+    delay: Duration,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl HostLimiter {
+    pub fn new(delay: Duration, jitter: Duration, max_per_host: usize) -> Self {
+        Self {
+            hosts: Mutex::new(HashMap::new()),
+            delay,
+            jitter,
+            max_per_host: max_per_host.max(1),
+        }
+    }
+
+    async fn state(&self, host: &str) -> Arc<HostState> {
+        let mut hosts = self.hosts.lock().await;
+
+        Arc::clone(hosts.entry(host.to_owned()).or_insert_with(|| {
+            Arc::new(HostState {
+                next_req: Mutex::new(Instant::now()),
+                permits: Arc::new(Semaphore::new(self.max_per_host)),
+            })
+        }))
+    }
+
+    /// Waits for a free slot for the host, the host's lock is only held while reserving the slot
+    /// and not while waiting for it or during the request
+    pub async fn acquire(&self, host: &str, crawl_delay: Option<Duration>) -> Res<HostPermit> {
+        let state = self.state(host).await;
+
+        let permit = Arc::clone(&state.permits)
+            .acquire_owned()
+            .await
+            .context("Failed to acquire per-host request permit")?;
+
+        let jitter = if self.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            self.jitter.mul_f64(rand::random_range(0.0..=1.0))
+        };
+        let delay = crawl_delay.map_or(self.delay + jitter, |d| d.max(self.delay + jitter));
+
+        let slot = {
+            let mut next_req = state.next_req.lock().await;
+            let slot = (*next_req).max(Instant::now());
+            *next_req = slot + delay;
+
+            slot
+        };
+
+        let now = Instant::now();
+        if now < slot {
+            sleep(slot - now).await;
+        }
+
+        Ok(HostPermit {
+            state,
+            delay,
+            _permit: permit,
+        })
+    }
+
+    /// Delays every request to the host until `time` has passed (e.g. after a 429 response)
+    pub async fn pause(&self, host: &str, time: Duration) {
+        let state = self.state(host).await;
+        let mut next_req = state.next_req.lock().await;
+
+        *next_req = (*next_req).max(Instant::now() + time);
+    }
+}
+
+impl HostPermit {
+    /// Ends the request, making sure the next one to the host waits the full delay after it
+    pub async fn release(self) {
+        let mut next_req = self.state.next_req.lock().await;
+
+        *next_req = (*next_req).max(Instant::now() + self.delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{error::Res, limiter::HostLimiter};
+
+    #[tokio::test]
+    async fn test_per_host_delay() -> Res<()> {
+        let limiter = HostLimiter::new(Duration::from_millis(100), Duration::ZERO, 2);
+        let start = Instant::now();
+
+        let first = limiter.acquire("https://a.example.com", None).await?;
+        let other_host = limiter.acquire("https://b.example.com", None).await?;
+
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // A second permit is free but the slot is still spaced from the first request
+        let second = limiter.acquire("https://a.example.com", None).await?;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        first.release().await;
+        other_host.release().await;
+        second.release().await;
+
+        Ok(())
+    }
+}
@@ -32,15 +32,101 @@
 //! crawn --include-text https://example.com | sed -i 's/[^\r]\n/\r\n/g' | jq -s '.' | cat > output.json
 //! ```                                                                                                  
 //!
+//! - Resumable crawl (state saved to disk after every batch):
+//! ```bash
+//! crawn --state-dir .crawn-state https://example.com > output.ndjson
+//! crawn --state-dir .crawn-state --resume >> output.ndjson
+//! ```
+//!
+//! - Seed from sitemaps (robots.txt `Sitemap:` lines or /sitemap.xml, including sitemap indexes and gzipped sitemaps):
+//! ```bash
+//! crawn --sitemaps https://example.com > output.ndjson
+//! ```
+//!
+//! - Custom concurrency (workers and requests per host):
+//! ```bash
+//! crawn -c 16 --max-per-host 4 --delay 100 https://example.com > output.ndjson
+//! ```
+//!
+//! - Custom User-Agent, headers and cookies (cookies set by the server are kept for the rest of the crawl):
+//! ```bash
+//! crawn -A 'MyBot/1.0 (+https://example.com/bot)' -H 'Accept-Language: en' --cookie 'session=abc123' https://example.com > output.ndjson
+//! crawn --cookie-jar cookies.txt https://example.com > output.ndjson
+//! ```
+//!
+//! - Authenticated crawl (credentials are only sent to the host they were configured for):
+//! ```bash
+//! crawn --auth user:pass https://wiki.example.com > output.ndjson
+//! CRAWN_BEARER_TOKEN=token crawn https://staging.example.com > output.ndjson
+//! crawn --netrc https://wiki.example.com > output.ndjson
+//! ```
+//!
+//! - Through a proxy (or rotating through a list of proxies, one URL per line):
+//! ```bash
+//! crawn --proxy socks5h://127.0.0.1:1080 --no-proxy intranet.example.com https://example.com > output.ndjson
+//! crawn --proxy-list proxies.txt https://example.com > output.ndjson
+//! ```
+//!
+//! - Scoped crawl (rules are evaluated in order, the first match wins):
+//! ```bash
+//! crawn --include-subdomains --path-prefix /docs --exclude '*.pdf' --include 're:/docs/v[0-9]+/' https://example.com > output.ndjson
+//! ```
+//!
+//! - Keyword relevance (only follow links whose URL or link text mentions the keywords):
+//! ```bash
+//! crawn --keywords tokio,async --relevance anchor --min-keyword-matches 2 https://example.com/rust > output.ndjson
+//! ```
+//!
+//! - Best-first crawl with a page budget:
+//! ```bash
+//! crawn --strategy best-first --keywords tokio,async --max-pages 100 https://example.com/rust > output.ndjson
+//! ```
+//!
+//! - Custom fields from CSS selectors (`@attr` extracts an attribute instead of the text):
+//! ```bash
+//! crawn --field 'price=.product-price' --field 'author=meta[name=author]@content' --field-all 'breadcrumbs=nav.breadcrumbs a' https://example.com > output.ndjson
+//! ```
+//!
+//! - URL normalization (query params are always sorted, `--strip-params` replaces the default list of tracking params like `utm_*`):
+//! ```bash
+//! crawn --strip-params 'utm_*,ref,sessionid' --trailing-slash strip --strip-index https://example.com > output.ndjson
+//! ```
+//!
+//! - Local dev servers (IP addresses and ports work like domains, the crawl stays on the base URL's port):
+//! ```bash
+//! crawn http://127.0.0.1:8080/ --allow-domain 127.0.0.1:8080 --allow-domain '[::1]:3000' > output.ndjson
+//! ```
+//!
+//! - Skip near-duplicate pages and their links (pages within 4 differing SimHash bits):
+//! ```bash
+//! crawn --skip-near-duplicates --near-duplicate-distance 4 https://example.com > output.ndjson
+//! ```
+//!
+//! - Archive the raw HTTP exchanges for replay (pywb, warcio) next to the usual output, starting a new file every 500 MB:
+//! ```bash
+//! crawn --warc docs.warc.gz --warc-max-size 500000000 https://docs.example.com > output.ndjson
+//! ```
+//!
+//! - Write zstd-compressed output to files of 10,000 pages each (`pages.ndjson.zst`, `pages-00001.ndjson.zst`, ...), each file keeps a `.part` suffix until it is complete (`--resume` keeps the files of the interrupted run and continues in the next one):
+//! ```bash
+//! crawn --include-content --output pages.ndjson.zst --rotate-pages 10000 https://example.com
+//! ```
+//!
 //! ---
 //!
 //! ## Output Format
 //!
-//! Results are written as NDJSON (newline-delimited JSON):
+//! Results are written to Stdout (or the `--output` file) as NDJSON (newline-delimited JSON), every record has the page's fetch details:
 //! ```json
-//! {"URL": "https://example.com", "Title": "Example Domain", "Links": 12}
-//! {"URL": "https://example.com/about", "Title": "About Us", "Links": 9}
-//! {"URL": "https://example.com/contact", "Title": "Contact", "Links": 48}
+//! {"URL": "https://example.com", "Title": "Example Domain", "Links": 12, "Status": 200, "FinalURL": "https://example.com/", "Depth": 0, "Referrer": null, "ContentType": "text/html; charset=UTF-8", "ContentLength": 1256, "ResponseTimeMs": 143, "FetchedAt": "2026-01-02T03:04:05Z"}
+//! {"URL": "https://example.com/about", "Title": "About Us", "Links": 9, "Status": 200, "FinalURL": "https://example.com/about/", "Depth": 1, "Referrer": "https://example.com", "ContentType": "text/html; charset=UTF-8", "ContentLength": 4810, "ResponseTimeMs": 97, "FetchedAt": "2026-01-02T03:04:06Z"}
+//! ```
+//!
+//! `FinalURL` is the URL after redirects, `ContentLength` the body size in bytes as received, `ResponseTimeMs` runs from sending the request to receiving the whole body and `FetchedAt` is in UTC. The examples below leave these keys out for brevity.
+//!
+//! - With `--fields URL,Status,Depth,Referrer` only those keys are written, in that order (`--field` names can be selected too):
+//! ```json
+//! {"URL": "https://example.com/about", "Status": 200, "Depth": 1, "Referrer": "https://example.com"}
 //! ```
 //!
 //! - With `--include-text`:
@@ -53,32 +139,100 @@
 //! {"URL": "https://example.com", "Title": "Example Domain", "Links": 30, "Content": "<!DOCTYPE html>\n<html>..."}
 //! ```
 //!
+//! - URLs discovered through `--sitemaps` keep their sitemap metadata:
+//! ```json
+//! {"URL": "https://example.com/orphan", "Title": "Orphan", "Links": 3, "Lastmod": "2026-01-01", "Priority": 0.8}
+//! ```
+//!
+//! - Pages fetched through a proxy (`--proxy`, `--proxy-list` or the `HTTP_PROXY`/`HTTPS_PROXY` env vars) say which one served them (credentials removed):
+//! ```json
+//! {"URL": "https://example.com/about", "Title": "About Us", "Links": 9, "Proxy": "http://proxy.corp:3128"}
+//! ```
+//!
+//! - With `--field`/`--field-all` (a missing `--field` is `null`, `--field-all` is always an array, names must be unique and can't be a built-in key like `URL` or `Title`):
+//! ```json
+//! {"URL": "https://example.com/product", "Title": "Product", "Links": 14, "price": "$19.99", "author": null, "breadcrumbs": ["Home", "Products"]}
+//! ```
+//!
+//! - Pages that declare a different `<link rel="canonical">` are written under the canonical URL with the URLs they were found under (an alias found after the canonical's record was written is only logged, aliases of a canonical page that ends without a record are written under their own URL):
+//! ```json
+//! {"URL": "https://example.com/article", "Title": "Article", "Links": 18, "Aliases": ["https://example.com/article?utm_source=feed", "https://example.com/print/article"]}
+//! ```
+//!
+//! - Pages whose text nearly matches a page already written (paginated listings, print views) point to it, `DuplicateOf` is `null` for every other page (pages under 20 words aren't compared):
+//! ```json
+//! {"URL": "https://example.com/tags/rust", "Title": "Rust", "Links": 30, "DuplicateOf": null}
+//! {"URL": "https://example.com/tags/rust?page=2", "Title": "Rust", "Links": 31, "DuplicateOf": "https://example.com/tags/rust"}
+//! ```
+//!
+//! - With `--include-meta` (missing tags are `null`, the first of a repeated tag wins):
+//! ```json
+//! {"URL": "https://example.com/docs/async", "Title": "Async", "Links": 21, "Meta": {"Description": "Async Rust guide", "Robots": "index, follow", "Canonical": "https://example.com/docs/async", "Lang": "en", "OpenGraph": {"title": "Async Rust", "type": "article"}, "Twitter": {"card": "summary"}, "Hreflang": {"de": "https://example.de/docs/async"}}}
+//! ```
+//!
+//! - With `--format csv --fields URL,Title,Status,Aliases` (also `json` for a single array, `tsv` and `markdown`), every row has the same columns, lists and objects are written as JSON:
+//! ```csv
+//! URL,Title,Status,Aliases
+//! https://example.com,Example Domain,200,
+//! https://example.com/about,About Us,200,"[""https://example.com/about-us""]"
+//! ```
+//!
+//! - With `--errors failures.ndjson`, every page that failed gets a record there (`ErrorKind` is `HttpStatus`, `Timeout`, `Connect`, `Redirect`, `Body`, `Network`, `Io`, ..., `Status` is `null` when the server didn't respond):
+//! ```json
+//! {"URL": "https://example.com/old-page", "Error": "Failed to fetch URL: https://example.com/old-page after 1 attempt(s) -> Server returned status code: 404 Not Found\nCause: ...", "ErrorKind": "HttpStatus", "Status": 404, "Referrer": "https://example.com/blog", "Depth": 2}
+//! ```
+//!
 //! ---
 //!
 //! ## How It Works
 //!
-//! 1. BFS Crawling:
+//! 1. Crawling:
 //! - Starts at the seed URL (depth 0)
 //! - Discovers links on each page
-//! - Processes links level-by-level (breadth-first)
-//! - Stops at max_depth (default: 3)
+//! - Processes links level-by-level (`--strategy bfs`, default), most recent first (`dfs`) or highest score first (`best-first`)
+//! - Best-first scores URLs by keyword matches of their path and link text plus sitemap priority, minus 0.25 per level of depth
+//! - `--max-pages` caps the number of fetched pages, so with `best-first` the budget goes to the most relevant pages
+//! - Every queued URL carries its own depth and parent page, so workers never wait for a level to finish
+//! - Stops at max_depth (default: 4)
 //! - Uses `tokio::task::spawn` and `tokio::task::spawn_blocking` for concurrent processing
+//! - Runs `--concurrency` workers (default: 9, or 6 with `--include-content`/`--include-text`), idle
+//!   workers sleep until new URLs are queued and all of them exit once nothing is left to crawl
 //!
-//! 2. Keyword Filtering:
-//! - Extracts "keywords" from URL paths (sanitized, lowercased)
-//! - Splits by /, -, _ (e.g., /rust-tutorials/async → ["rust", "tutorials", "async"])
-//! - Filters stop words, numbers, short words (<3 chars)
-//! - Matches candidate URLs against base keywords
-//! - Result: Only crawls relevant pages, skips off-topic content
+//! 2. Scope:
+//! - Only the base URL's domain by default, `--allow-domain` (repeatable) and `--include-subdomains` widen it
+//! - `--path-prefix` (repeatable) limits the crawl to matching paths
+//! - `--include`/`--exclude` rules (globs, or regexes prefixed with `re:`) are matched against the whole URL in the given order, the first match wins
+//! - With any `--include` rule, URLs that match no rule are skipped
+//! - Out-of-scope links are dropped before they're queued, redirects that leave the scope are logged with the rule that rejected them
 //!
-//! 3. Rate Limiting:
-//! - Random range between 300 - 600ms
+//! 3. Keyword Relevance:
+//! - Extracts "keywords" from URL paths (sanitized, lowercased)
+//! - Splits by /, -, _, . (e.g., /rust-tutorials/async.html → ["rust", "tutorials", "async"])
+//! - Filters stop words, file extensions, numbers, short words (<3 chars)
+//! - Seed keywords come from the base URL's path plus `--keywords` (only `--keywords` with `--no-seed-keywords`)
+//! - A URL is crawled if at least `--min-keyword-matches` (default: 1) of its keywords are seed keywords or generic ones (tutorial, guide, blog)
+//! - `--relevance anchor` also matches the link text, `--relevance off` disables filtering (default: url)
+//! - Result: Only crawls relevant pages, skips off-topic content (the final summary shows how many URLs were filtered)
+//!
+//! 4. Rate Limiting:
+//! - Per host: requests to different hosts never wait on each other
+//! - Delay between requests to the same host: `--delay` + random `--jitter` (default: 300 - 600ms)
+//! - At most `--max-per-host` requests in flight per host (default: 1)
 //! - Prevents server overload and IP bans
-//! - Configurable via code (not exposed as CLI flag yet)
 //!
-//! 4. Error Handling:
+//! 5. robots.txt:
+//! - Fetched once per host and cached for the rest of the crawl
+//! - Disallowed URLs are skipped (and logged) before they are fetched
+//! - Matched against the product token of `--user-agent` (default: `crawn`)
+//! - `Crawl-delay` raises the delay between requests for that host
+//! - Unreachable robots.txt (5xx, network errors after `--retries`) disallows the whole host for a minute, then it is fetched again
+//! - Disabled with `--ignore-robots`
+//!
+//! 6. Error Handling:
 //! - Network errors: Logged as warnings, crawling continues
-//! - HTTP 404/500: Skipped, logged as warnings
+//! - Timeouts, connection errors, HTTP 429 and 5xx: Retried `--retries` times (default: 2) with exponential backoff starting at `--retry-backoff` ms (default: 500), honouring `Retry-After` (capped at 120 seconds)
+//! - Other HTTP errors (e.g. 404) and exhausted retries: Skipped, logged as warnings
+//! - Non-HTML responses (`--content-types`, default: HTML/XHTML) and bodies over `--max-body-size` (default: 10 MiB): Skipped before parsing, logged; the body is streamed and dropped once it passes the limit
 //! - Parse failures: Logged, returns empty JSON
 //! - Fatal errors: Printed to stdout with full context chain
 //!
@@ -124,9 +278,9 @@
 //!
 //! ## Limitations
 //!
-//! - Same-domain only (no external links, by design)
+//! - Same-domain by default (widen with `--allow-domain` and `--include-subdomains`)
 //! - No JavaScript rendering (static HTML only)
-//! - No authentication (public pages only)
+//! - No login forms (use `--auth`, `--bearer-token`, `--netrc` or `--cookie` for authenticated pages)
 //!
 //! ---
 //!
@@ -134,22 +288,31 @@
 //!
 //! crawn is licensed under the **MIT** license.
 
-use std::sync::atomic::{AtomicU8, AtomicUsize};
+use std::collections::HashSet;
+use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, LazyLock};
-use std::time::Duration;
 
-use clap::Parser;
 use owo_colors::OwoColorize;
 use resext::ctx;
 use tokio::io::{AsyncReadExt, stdin};
 use tokio::sync::Mutex;
 
+mod auth;
 mod cli;
+mod cookies;
 mod crawler;
 mod error;
 mod fetch;
+mod limiter;
 mod output;
+mod proxy;
 mod repo;
+mod robots;
+mod scheduler;
+mod scope;
+mod simhash;
+mod sitemap;
+mod warc;
 
 use crate::fetch::*;
 use crawler::*;
@@ -158,20 +321,49 @@ use scraper::{Html, Selector};
 use url::Url;
 
 use crate::error::{LOG_TIMESTAMP_FORMAT, Log, Res, ResErr, ResExt, flush_logger};
-use crate::output::{flush_writer, write_output};
-
-pub static ARGS: LazyLock<cli::Args> = LazyLock::new(cli::Args::parse);
+use crate::output::{ErrorRecord, Record, flush_writer, write_error};
+use crate::scheduler::Scheduler;
+use crate::scope::Scope;
+use crate::simhash::{NearDuplicates, simhash};
+use crate::sitemap::discover_sitemap_urls;
+use crate::warc::{flush_warc, write_metadata};
+
+pub static ARGS: LazyLock<cli::Args> = LazyLock::new(cli::Args::parse_ordered);
 static CRAWLED: LazyLock<Arc<AtomicUsize>> = LazyLock::new(|| Arc::new(AtomicUsize::new(0)));
 static SUCCESSES: LazyLock<Arc<AtomicUsize>> = LazyLock::new(|| Arc::new(AtomicUsize::new(0)));
+static FILTERED: LazyLock<Arc<AtomicUsize>> = LazyLock::new(|| Arc::new(AtomicUsize::new(0)));
 
 async fn run() -> Res<()> {
     let args = &*ARGS;
-    let repo = Arc::new(Mutex::new(InMemoryRepo::default()));
-    let client = Arc::new(CrawnClient::new()?);
-    let curr_depth = Arc::new(AtomicU8::new(0));
-    let pending = Arc::new(AtomicUsize::new(0));
-    let crawled = Arc::clone(&*CRAWLED);
-    let successes = Arc::clone(&*SUCCESSES);
+
+    if let Some(dir) = &args.state_dir {
+        let mut repo = FileRepo::open(dir.clone(), args.resume)
+            .await
+            .context(ctx!(
+                "Failed to open crawl state directory: {}",
+                dir.to_string_lossy()
+            ))?;
+
+        let url = match repo.seed() {
+            Some(seed) if args.url.is_none() => seed.to_owned(),
+            _ => read_base_url().await?,
+        };
+        repo.set_seed(&url)
+            .await
+            .context("Failed to save base URL to crawl state")?;
+
+        let resume = args.resume && repo.has_pending();
+
+        crawl(Arc::new(Mutex::new(repo)), url, resume).await
+    } else {
+        let url = read_base_url().await?;
+
+        crawl(Arc::new(Mutex::new(InMemoryRepo::default())), url, false).await
+    }
+}
+
+async fn read_base_url() -> Res<String> {
+    let args = &*ARGS;
 
     let mut url = String::new();
     if args.url.is_some() {
@@ -194,11 +386,85 @@ async fn run() -> Res<()> {
         }
     }
 
+    Ok(url)
+}
+
+/// Crawls from the base URL, skipping the base URL itself if `resume` is set since the repo
+/// already holds the frontier of a previous crawl
+async fn crawl<R: UrlRepo + Send + 'static>(
+    repo: Arc<Mutex<R>>,
+    url: String,
+    resume: bool,
+) -> Res<()> {
+    let args = &*ARGS;
+    let max_depth = args.max_depth.unwrap_or(4);
+    let max_pages = args.max_pages.unwrap_or(usize::MAX);
+    let crawled = Arc::clone(&*CRAWLED);
+    let successes = Arc::clone(&*SUCCESSES);
+    let filtered = Arc::clone(&*FILTERED);
+
     let base = Url::parse(&url).context("Failed to parse base URL")?;
+    let client = Arc::new(CrawnClient::new(&base)?);
+
+    let domains = if args.allow_domains.is_empty() {
+        let host = base.host_str().unwrap_or_default();
+
+        // An explicit port keeps the crawl on the same server, e.g. one of several local dev servers
+        vec![match base.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        }]
+    } else {
+        args.allow_domains.clone()
+    };
+
+    let scope = Arc::new(
+        Scope::new(
+            domains,
+            args.include_subdomains,
+            args.path_prefixes.clone(),
+            &args.scope_rules,
+        )
+        .context("Failed to build crawl scope")?,
+    );
+
+    let canonicals = Arc::new(Canonicals::new(
+        Arc::clone(&scope),
+        UrlRules::from_args(args),
+    ));
+    let near_duplicates = Arc::new(NearDuplicates::new(
+        args.near_duplicate_distance.unwrap_or(6),
+    ));
+
+    let mut keywords = if args.no_seed_keywords {
+        HashSet::new()
+    } else {
+        get_keywords(&base)
+    };
+    keywords.extend(args.keywords.iter().map(|kw| kw.trim().to_lowercase()));
+
+    let relevance = Arc::new(Relevance {
+        mode: args.relevance.unwrap_or(RelevanceMode::Url),
+        keywords,
+        min_matches: args.min_keyword_matches.unwrap_or(1),
+    });
+
+    if relevance.mode != RelevanceMode::Off && relevance.keywords.is_empty() {
+        String::from(
+            "No keywords in base URL's path or --keywords, relevance filtering is turned off",
+        )
+        .log()
+        .await?;
+    }
 
-    let base_keywords = Arc::new(get_keywords(&base));
+    {
+        let relevance = Arc::clone(&relevance);
 
-    let base_domain = Arc::new(base.domain().unwrap_or_default().to_owned());
+        repo.lock().await.order_by(
+            args.strategy.unwrap_or_default(),
+            Arc::new(move |entry: &FrontierEntry| relevance.score(entry)),
+        );
+    }
 
     let selectors = Arc::new(Selectors {
         anchor: Selector::parse("a[href]").context(ctx!(
@@ -211,163 +477,314 @@ async fn run() -> Res<()> {
             "`<title>`".yellow()
         ))?,
 
-        body: if args.include_text {
-            Some(Selector::parse("body").context(ctx!(
-                "Failed to parse selector for HTML 'body' tag: {}",
-                "`<body>`".yellow()
-            ))?)
+        body: Selector::parse("body").context(ctx!(
+            "Failed to parse selector for HTML 'body' tag: {}",
+            "`<body>`".yellow()
+        ))?,
+
+        fields: Field::parse_all(&args.fields, &args.fields_all)?,
+
+        canonical: if args.ignore_canonical {
+            None
+        } else {
+            Some(
+                Selector::parse("link[rel][href]")
+                    .context("Failed to parse selector for HTML 'link' tag")?,
+            )
+        },
+
+        meta: if args.include_meta {
+            Some(MetaSelectors::new()?)
         } else {
             None
         },
     });
 
-    crawled.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-    let content = fetch_url(&url, Arc::clone(&client))
-        .await
-        .context("Failed to fetch base URL")?;
+    if resume {
+        if args.verbose {
+            format!("Resuming crawl of base URL: {}", &url)
+                .log()
+                .await?;
+        }
+    } else {
+        // Links back to the base URL are normalized too, so it has to be for them to match
+        let url = canonicals
+            .normalize(base.clone())
+            .context("Failed to normalize base URL")?;
+        let seed = FrontierEntry::from(url.clone());
+
+        if !args.ignore_robots {
+            let robots = client.robots(&base).await?;
+
+            if !robots.is_allowed(&base) {
+                let hint = match &robots.error {
+                    Some(err) => format!(
+                        "robots.txt couldn't be fetched so the whole host is treated as \
+                         disallowed, use --ignore-robots to crawl it anyway\n{}",
+                        err
+                    ),
+                    None => String::from("Use --ignore-robots to crawl it anyway"),
+                };
 
-    successes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                return Err(ResErr::from_args(
+                    ctx!("Base URL is disallowed by robots.txt: {}", &url),
+                    hint,
+                ));
+            }
+        }
 
-    if args.verbose {
-        String::from("Fetched content from base URL").log().await?;
-    }
+        crawled.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let FetchedPage {
+            content,
+            url: page_url,
+            proxy,
+            warc_id,
+            info,
+        } = fetch_url(&url, Arc::clone(&client), &scope)
+            .await
+            .context("Failed to fetch base URL")?
+            .ok_or_else(|| {
+                ResErr::from_args(
+                    ctx!("Base URL is not a crawlable HTML page: {}", &url),
+                    String::from(
+                        "Check --content-types, --max-body-size and where it redirects to (--allow-domain)",
+                    ),
+                )
+            })?;
+
+        successes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+        if args.verbose {
+            String::from("Fetched content from base URL").log().await?;
+        }
 
-    let doc = Html::parse_document(&content);
+        let doc = Html::parse_document(&content);
 
-    let links = extract_links(&doc, Arc::new(base), &selectors.anchor);
-    let mut link_count = 0usize;
-    {
-        let mut rp = repo.lock().await;
-        rp.mark(url.clone())
-            .await
-            .context("Failed to mark base URL as visited")?;
+        let links = extract_links(&doc, Arc::new(page_url.clone()), &selectors.anchor);
+        let mut link_count = 0usize;
+
+        if let Some(warc_id) = &warc_id {
+            write_metadata(page_url.as_str(), warc_id, &outlinks(&links))
+                .await
+                .context("Failed to archive metadata of base URL")?;
+        }
 
-        for link in links {
-            let link = match_option!(link.log().await?);
-            let link = match_option!(normalize_url(link).log().await?);
+        let sitemap_entries = if args.sitemaps {
+            let robots = client.robots(&base).await?;
 
-            match_option!(rp.add(link).await.log().await?);
+            discover_sitemap_urls(&client, &base, &robots.sitemaps)
+                .await
+                .context("Failed to discover sitemaps of base URL")?
+        } else {
+            Vec::new()
+        };
+
+        let canonical = selectors
+            .canonical
+            .as_ref()
+            .and_then(|link_selector| extract_canonical(&doc, link_selector, &page_url));
+
+        let (canonical, checkpoint) = {
+            let mut rp = repo.lock().await;
+            rp.mark(url.clone())
+                .await
+                .context("Failed to mark base URL as visited")?;
+
+            // Nothing else has been seen yet so the base URL can't be an alias
+            let resolved = canonicals
+                .resolve(&mut *rp, &url, canonical)
+                .await?
+                .unwrap_or_else(|_| url.clone());
+
+            for link in links {
+                let (link, anchor) = match_option!(link.log().await?);
+                let link = match_option!(canonicals.link(link).log().await?.flatten());
+
+                match_option!(
+                    rp.add(seed.child(link).with_anchor(anchor))
+                        .await
+                        .log()
+                        .await?
+                );
+
+                link_count += 1;
+            }
+
+            for entry in sitemap_entries {
+                let link = match_option!(
+                    Url::parse(&entry.url)
+                        .context(ctx!("Failed to parse URL from sitemap: {}", &entry.url))
+                        .log()
+                        .await?
+                );
+                let link = match_option!(canonicals.link(link).log().await?.flatten());
+
+                match_option!(
+                    rp.add(FrontierEntry {
+                        url: link,
+                        depth: 1,
+                        ..entry
+                    })
+                    .await
+                    .log()
+                    .await?
+                );
+            }
+
+            (resolved, rp.checkpoint())
+        };
+
+        checkpoint
+            .save()
+            .await
+            .context("Failed to save crawl state after base URL")?;
 
-            link_count += 1;
+        let text = extract_text(&doc, &selectors.body);
+
+        // The first page can't be a near-duplicate, but later ones are compared against it
+        if let Some(fingerprint) = simhash(&text) {
+            near_duplicates.check(&canonical, fingerprint).await;
         }
-        rp.add(String::from("M")).await?;
-    }
-    curr_depth.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
-    let text = selectors
-        .body
-        .as_ref()
-        .map(|body_selector| extract_text(&doc, body_selector));
-    let title = extract_title(&doc, &selectors.title);
+        let title = extract_title(&doc, &selectors.title);
+        let fields = extract_fields(&doc, &selectors.fields);
+        let meta = selectors
+            .meta
+            .as_ref()
+            .map(|meta_selectors| extract_meta(&doc, meta_selectors, &page_url));
 
-    let content = if args.include_content {
-        Some(content)
-    } else {
-        None
-    };
+        let aliases = if canonical != url {
+            vec![url.clone()]
+        } else {
+            Vec::new()
+        };
+
+        canonicals
+            .write(Record {
+                entry: FrontierEntry {
+                    url: canonical,
+                    ..seed
+                },
+                title,
+                links: link_count,
+                text: args.include_text.then_some(text),
+                content: args.include_content.then_some(content),
+                proxy,
+                aliases,
+                duplicate_of: None,
+                fields,
+                meta,
+                fetch: info,
+            })
+            .await
+            .log()
+            .await?;
+    }
 
-    write_output(url.clone(), title, link_count, text, content)
-        .await
-        .log()
-        .await?;
+    let task_count = args
+        .concurrency
+        .unwrap_or(if args.include_content || args.include_text {
+            6
+        } else {
+            9
+        });
 
-    let task_count = if args.include_content || args.include_text {
-        6
-    } else {
-        9
-    };
+    let scheduler = Scheduler::new(Arc::clone(&repo));
 
     let mut tasks = Vec::new();
     for _ in 0..task_count {
         let repo = Arc::clone(&repo);
-        let base_keywords = Arc::clone(&base_keywords);
-        let base_domain = Arc::clone(&base_domain);
+        let scheduler = Arc::clone(&scheduler);
+        let relevance = Arc::clone(&relevance);
+        let filtered = Arc::clone(&filtered);
         let selectors = Arc::clone(&selectors);
         let client = Arc::clone(&client);
-        let curr_depth = Arc::clone(&curr_depth);
-        let pending = Arc::clone(&pending);
+        let canonicals = Arc::clone(&canonicals);
+        let near_duplicates = Arc::clone(&near_duplicates);
         let crawled = Arc::clone(&crawled);
         let successes = Arc::clone(&successes);
 
         let task: tokio::task::JoinHandle<Res<()>> = tokio::task::spawn(async move {
-            loop {
-                if curr_depth.load(std::sync::atomic::Ordering::SeqCst)
-                    > args.max_depth.unwrap_or(4)
-                {
-                    break;
-                }
-
-                let work_item = {
-                    let mut repo_guard = repo.lock().await;
-                    repo_guard.pop().await.log().await?.unwrap_or(None)
-                };
+            while let Some(job) = scheduler.next().await? {
+                let entry = job.entry.clone();
+                let url = entry.url.clone();
+                let other = Url::parse(&url).context(ctx!("Failed to parse URL: {}", &url))?;
+
+                // Links are scope checked before they're queued and redirects in `fetch_url`
+                if entry.depth > max_depth {
+                    // Only possible when resuming a crawl with a lower --max-depth
+                } else if let Err(matches) = relevance.check(&other, entry.anchor.as_deref()) {
+                    filtered.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+                    format!(
+                        "Skipped irrelevant URL: {} ({} of {} required keyword matches)",
+                        &url, matches, relevance.min_matches
+                    )
+                    .log()
+                    .await?;
+                } else if args.ignore_robots || client.robots(&other).await?.is_allowed(&other) {
+                    let has_budget = crawled
+                        .fetch_update(
+                            std::sync::atomic::Ordering::SeqCst,
+                            std::sync::atomic::Ordering::SeqCst,
+                            |n| (n < max_pages).then_some(n + 1),
+                        )
+                        .is_ok();
+
+                    // The URL stays unvisited so a resumed crawl with a bigger budget picks it up
+                    if !has_budget {
+                        break;
+                    }
 
-                match work_item {
-                    None => {
-                        if pending.load(std::sync::atomic::Ordering::SeqCst) > 0 {
-                            tokio::time::sleep(Duration::from_millis(100)).await;
-                        } else {
-                            break;
-                        }
+                    let can_extract = entry.depth < max_depth;
+
+                    let res = worker(
+                        Arc::clone(&repo),
+                        Arc::clone(&selectors),
+                        Arc::clone(&client),
+                        Arc::clone(&canonicals),
+                        Arc::clone(&near_duplicates),
+                        entry,
+                        can_extract,
+                    )
+                    .await;
+
+                    if let Err(err) = &res {
+                        write_error(ErrorRecord::new(job.entry.clone(), err))
+                            .await
+                            .context(ctx!("Failed to write error record for URL: {}", &url))
+                            .log()
+                            .await?;
                     }
 
-                    Some(url) => {
-                        if &url == "M" {
-                            if pending.load(std::sync::atomic::Ordering::SeqCst) > 0 {
-                                #[allow(clippy::unit_arg)]
-                                repo.lock().await.kick(url).await.log().await?.unwrap_or({
-                                    tokio::time::sleep(Duration::from_millis(100)).await;
-                                });
-
-                                tokio::time::sleep(Duration::from_millis(100)).await;
-                            } else {
-                                repo.lock()
-                                    .await
-                                    .add(url)
-                                    .await
-                                    .log()
-                                    .await?
-                                    .unwrap_or_default();
-
-                                curr_depth.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                            }
-                        } else {
-                            pending.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-
-                            let can_extract = curr_depth.load(std::sync::atomic::Ordering::SeqCst)
-                                < args.max_depth.unwrap_or(4);
-
-                            let other =
-                                Url::parse(&url).context(ctx!("Failed to parse URL: {}", &url))?;
-
-                            if should_crawl(
-                                Arc::clone(&base_domain),
-                                Arc::clone(&base_keywords),
-                                &other,
-                            ) {
-                                crawled.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-
-                                let is_success = worker(
-                                    Arc::clone(&repo),
-                                    Arc::clone(&selectors),
-                                    Arc::clone(&client),
-                                    url,
-                                    can_extract,
-                                )
-                                .await
-                                .log()
-                                .await?
-                                .is_some();
-
-                                if is_success {
-                                    successes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                                }
-                            }
-
-                            pending.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
-                        }
+                    let is_success = res.log().await?.is_some();
+
+                    if is_success {
+                        successes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
                     }
+                } else {
+                    format!("Skipped URL disallowed by robots.txt: {}", &url)
+                        .log()
+                        .await?;
                 }
+
+                let orphans = canonicals.finish(&url).await;
+                canonicals.write_orphans(orphans).await.log().await?;
+
+                let checkpoint = {
+                    let mut rp = repo.lock().await;
+                    rp.mark(url.clone()).await.log().await?;
+                    rp.checkpoint()
+                };
+
+                checkpoint
+                    .save()
+                    .await
+                    .context(ctx!("Failed to save crawl state after URL: {}", &url))
+                    .log()
+                    .await?;
+
+                drop(job);
             }
 
             Ok(())
@@ -380,7 +797,18 @@ async fn run() -> Res<()> {
         task.await.context("Failed to spawn concurrent worker")??;
     }
 
+    // Aliases of canonical pages that were never crawled (--max-pages, --max-depth)
+    let orphans = canonicals.finish_all().await;
+    canonicals.write_orphans(orphans).await?;
+
+    let checkpoint = repo.lock().await.checkpoint();
+    checkpoint
+        .save()
+        .await
+        .context("Failed to save final crawl state")?;
+
     flush_writer().await?;
+    flush_warc().await?;
     flush_logger().await
 }
 
@@ -414,6 +842,15 @@ async fn main() -> std::process::ExitCode {
                     }
                 }
             );
+
+            let filtered = FILTERED.load(std::sync::atomic::Ordering::Relaxed);
+            if filtered > 0 {
+                eprintln!(
+                    "Filtered {} irrelevant URLs",
+                    filtered.bright_yellow().bold()
+                );
+            }
+
             std::process::ExitCode::SUCCESS
         }
         Err(e) => {
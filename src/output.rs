@@ -1,15 +1,356 @@
+use resext::ctx;
+use std::{
+    borrow::Cow,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use flate2::{Compression, write::GzEncoder};
+use serde::{
+    Serialize, Serializer,
+    ser::{SerializeMap, SerializeSeq},
+};
+use serde_json::ser::{CompactFormatter, Formatter};
 use tokio::{
+    fs::{File, OpenOptions},
     io::{AsyncWriteExt, BufWriter, Stdout, stdout},
     sync::{Mutex, OnceCell},
 };
 
-use crate::error::{Res, ResExt};
+use crate::{
+    FrontierEntry,
+    crawler::FieldValue,
+    error::{Res, ResErr, ResExt},
+    fetch::{FetchInfo, PageMeta},
+};
+
+/// How records are written to Stdout
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per line
+    #[default]
+    Ndjson,
+    /// A single JSON array of objects
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row, tabs, newlines and backslashes are escaped
+    Tsv,
+    /// A Markdown table
+    Markdown,
+}
+
+impl OutputFormat {
+    /// Whether every record is written as a row with the same columns
+    fn is_tabular(self) -> bool {
+        matches!(self, Self::Csv | Self::Tsv | Self::Markdown)
+    }
+
+    /// Written once before the first record, tabular formats take their columns from it
+    fn header(self, record: &Record, select: Option<&[String]>) -> Res<Vec<u8>> {
+        let columns = record.entries(true, select).into_iter().map(|(key, _)| key);
+
+        match self {
+            Self::Ndjson => Ok(Vec::new()),
+            Self::Json => Ok(Vec::from(b"[")),
+            Self::Csv => csv_row(columns),
+            Self::Tsv => Ok(tsv_row(columns)),
+            Self::Markdown => {
+                let columns: Vec<Cow<str>> = columns.collect();
+                let mut header = markdown_row(&columns);
+                header.extend(markdown_row(columns.iter().map(|_| "---")));
+
+                Ok(header)
+            }
+        }
+    }
+
+    /// Written before every record
+    fn separator(self, first: bool) -> &'static [u8] {
+        match self {
+            Self::Json if first => b"\n  ",
+            Self::Json => b",\n  ",
+            _ => b"",
+        }
+    }
+
+    fn record(self, record: &Record, select: Option<&[String]>) -> Res<Vec<u8>> {
+        if self.is_tabular() {
+            let cells = record
+                .entries(true, select)
+                .into_iter()
+                .map(|(_, value)| value.to_cell())
+                .collect::<Res<Vec<String>>>()?;
+
+            return match self {
+                Self::Csv => csv_row(cells),
+                Self::Tsv => Ok(tsv_row(cells)),
+                _ => Ok(markdown_row(cells)),
+            };
+        }
+
+        let mut line = Vec::with_capacity(
+            record
+                .text
+                .as_ref()
+                .or(record.content.as_ref())
+                .map_or(1024, |t| t.len() + 512),
+        );
+
+        let mut ser = serde_json::Serializer::with_formatter(&mut line, SpacedFormatter);
+        Entries(record.entries(false, select))
+            .serialize(&mut ser)
+            .map_err(Box::new)
+            .context("Failed to serialize output entry")?;
+
+        if self == Self::Ndjson {
+            line.push(b'\n');
+        }
+
+        Ok(line)
+    }
+
+    /// Written once after the last record
+    fn footer(self, records: usize) -> &'static [u8] {
+        match self {
+            Self::Json if records == 0 => b"[]\n",
+            Self::Json => b"\n]\n",
+            _ => b"",
+        }
+    }
+}
+
+/// Compresses output in memory, the compressed bytes are then written to the file asynchronously
+enum Encoder {
+    Plain(Vec<u8>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Zstd(zstd::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    /// Picks the compression from the extension: '.gz', '.zst' or none
+    fn for_path(path: &Path) -> Res<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Ok(Self::Gzip(GzEncoder::new(
+                Vec::new(),
+                Compression::default(),
+            ))),
+            Some("zst") => Ok(Self::Zstd(
+                zstd::Encoder::new(Vec::new(), 0).context("Failed to create zstd encoder")?,
+            )),
+            _ => Ok(Self::Plain(Vec::new())),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Res<()> {
+        match self {
+            Self::Plain(buf) => {
+                buf.extend_from_slice(bytes);
+                Ok(())
+            }
+            Self::Gzip(encoder) => encoder.write_all(bytes),
+            Self::Zstd(encoder) => encoder.write_all(bytes),
+        }
+        .context("Failed to compress output")
+    }
+
+    /// Takes the bytes that are ready to be written
+    fn take(&mut self) -> Vec<u8> {
+        match self {
+            Self::Plain(buf) => std::mem::take(buf),
+            Self::Gzip(encoder) => std::mem::take(encoder.get_mut()),
+            Self::Zstd(encoder) => std::mem::take(encoder.get_mut()),
+        }
+    }
+
+    /// Ends the compressed stream, returning the remaining bytes
+    fn finish(self) -> Res<Vec<u8>> {
+        match self {
+            Self::Plain(buf) => Ok(buf),
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+        .context("Failed to finish compressed output")
+    }
+}
+
+/// `--output` file, written to `<path>.part` and renamed when complete so a file with the final
+/// name is never half-written
+struct OutputFile {
+    path: PathBuf,
+    /// Number of the current file, see `numbered_path`
+    index: u32,
+    file: BufWriter<File>,
+    encoder: Encoder,
+    /// Bytes written to the current file before compression
+    size: u64,
+}
+
+impl OutputFile {
+    /// Opens the crawl's first file, with `resume` the files of the previous run (finished or
+    /// `.part`) are kept and the output continues in the next numbered file
+    async fn open(path: PathBuf, resume: bool, buf_cap: usize) -> Res<Self> {
+        let taken = |index| {
+            let numbered = numbered_path(&path, index);
+            numbered.exists() || part_path(&numbered).exists()
+        };
 
-static WRITER: OnceCell<Mutex<BufWriter<Stdout>>> = OnceCell::const_new();
+        let mut index = 0;
+        while resume && taken(index) {
+            index += 1;
+        }
+
+        Self::create(path, index, buf_cap).await
+    }
+
+    /// Creates the `.part` file, an existing one holds the records of a crawl that didn't finish
+    /// and is never overwritten
+    async fn create(path: PathBuf, index: u32, buf_cap: usize) -> Res<Self> {
+        let part = part_path(&numbered_path(&path, index));
+        let file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&part)
+            .await
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(ResErr::from_args(
+                    ctx!(
+                        "Output file of an unfinished crawl already exists: {}",
+                        part.to_string_lossy()
+                    ),
+                    String::from("Use --resume to continue that crawl, or move the file away"),
+                ));
+            }
+            Err(err) => {
+                return Err(err).context(ctx!(
+                    "Failed to create output file: {}",
+                    part.to_string_lossy()
+                ));
+            }
+        };
+
+        Ok(Self {
+            encoder: Encoder::for_path(&path)?,
+            file: BufWriter::with_capacity(buf_cap, file),
+            path,
+            index,
+            size: 0,
+        })
+    }
+
+    async fn write_all(&mut self, bytes: &[u8]) -> Res<()> {
+        self.encoder.write(bytes)?;
+        self.size += bytes.len() as u64;
+
+        self.file
+            .write_all(&self.encoder.take())
+            .await
+            .context("Failed to write output file")
+    }
+
+    /// Finishes and renames the file, returns the next one when rotating
+    async fn finish(mut self, rotate: bool, buf_cap: usize) -> Res<Option<Self>> {
+        let encoder = std::mem::replace(&mut self.encoder, Encoder::Plain(Vec::new()));
+        let path = numbered_path(&self.path, self.index);
+        let part = part_path(&path);
+
+        self.file
+            .write_all(&encoder.finish()?)
+            .await
+            .context("Failed to write output file")?;
+        self.file
+            .flush()
+            .await
+            .context("Failed to flush output file")?;
+        self.file
+            .into_inner()
+            .sync_all()
+            .await
+            .context("Failed to flush output file")?;
+
+        tokio::fs::rename(&part, &path).await.context(ctx!(
+            "Failed to rename output file: {}",
+            part.to_string_lossy()
+        ))?;
+
+        if !rotate {
+            return Ok(None);
+        }
 
-async fn init_writer() -> &'static Mutex<BufWriter<Stdout>> {
+        Ok(Some(
+            Self::create(self.path, self.index + 1, buf_cap).await?,
+        ))
+    }
+}
+
+enum Sink {
+    Stdout(BufWriter<Stdout>),
+    /// `None` once finished
+    File(Option<Box<OutputFile>>),
+}
+
+struct Output {
+    sink: Sink,
+    format: OutputFormat,
+    /// Records in the current file
+    records: usize,
+    buf_cap: usize,
+}
+
+impl Output {
+    async fn write_all(&mut self, bytes: &[u8]) -> Res<()> {
+        match &mut self.sink {
+            Sink::Stdout(writer) => writer
+                .write_all(bytes)
+                .await
+                .context("Failed to write output entry"),
+            Sink::File(Some(file)) => file.write_all(bytes).await,
+            Sink::File(None) => Ok(()),
+        }
+    }
+
+    /// Whether the next record goes to a new file because of `--rotate-size`/`--rotate-pages`
+    fn should_rotate(&self) -> bool {
+        let args = &*crate::ARGS;
+
+        match &self.sink {
+            Sink::File(Some(file)) => {
+                self.records > 0
+                    && (args.rotate_pages.is_some_and(|pages| self.records >= pages)
+                        || args.rotate_size.is_some_and(|size| file.size >= size))
+            }
+            _ => false,
+        }
+    }
+
+    /// Writes the footer and finishes the current file, opening the next one when rotating
+    async fn finish(&mut self, rotate: bool) -> Res<()> {
+        let footer = self.format.footer(self.records);
+        self.write_all(footer)
+            .await
+            .context("Failed to write output footer")?;
+        self.records = 0;
+
+        match &mut self.sink {
+            Sink::Stdout(writer) => writer.flush().await.context("Failed to flush writer"),
+            Sink::File(file) => {
+                if let Some(current) = file.take() {
+                    *file = current.finish(rotate, self.buf_cap).await?.map(Box::new);
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+static WRITER: OnceCell<Mutex<Output>> = OnceCell::const_new();
+
+async fn init_writer() -> Res<&'static Mutex<Output>> {
     WRITER
-        .get_or_init(async || {
+        .get_or_try_init(async || -> Res<Mutex<Output>> {
             let args = &*crate::ARGS;
             let buf_cap = if args.include_content {
                 1024 * 16
@@ -19,112 +360,689 @@ async fn init_writer() -> &'static Mutex<BufWriter<Stdout>> {
                 256
             };
 
-            Mutex::new(BufWriter::with_capacity(buf_cap, stdout()))
+            let sink = match &args.output {
+                Some(path) => Sink::File(Some(Box::new(
+                    OutputFile::open(path.clone(), args.resume, buf_cap).await?,
+                ))),
+                None => Sink::Stdout(BufWriter::with_capacity(buf_cap, stdout())),
+            };
+
+            Ok(Mutex::new(Output {
+                sink,
+                format: args.format.unwrap_or_default(),
+                records: 0,
+                buf_cap,
+            }))
         })
         .await
 }
 
+static ERRORS: OnceCell<Option<Mutex<Option<OutputFile>>>> = OnceCell::const_new();
+
+/// The `--errors` file, `None` without it
+async fn init_errors() -> Res<Option<&'static Mutex<Option<OutputFile>>>> {
+    let errors = ERRORS
+        .get_or_try_init(async || -> Res<Option<Mutex<Option<OutputFile>>>> {
+            match &crate::ARGS.errors {
+                Some(path) => Ok(Some(Mutex::new(Some(
+                    OutputFile::open(path.clone(), crate::ARGS.resume, 256).await?,
+                )))),
+                None => Ok(None),
+            }
+        })
+        .await?;
+
+    Ok(errors.as_ref())
+}
+
+/// Finishes the output (closing the JSON array) and flushes it, `--output` and `--errors` files
+/// get their final name only now
 pub async fn flush_writer() -> Res<()> {
-    init_writer()
-        .await
-        .lock()
-        .await
-        .flush()
-        .await
-        .context("Failed to flush writer")
-}
-
-pub async fn write_output(
-    url: String,
-    title: String,
-    links: usize,
-    text: Option<String>,
-    content: Option<String>,
-) -> Res<()> {
-    let line = tokio::task::spawn_blocking(move || {
-        let mut buf = Vec::with_capacity(256);
-        let mut line = Vec::with_capacity(text.as_ref().map_or(1024, |t| t.len() + 512));
-
-        line.extend_from_slice(b"{\"URL\": \"");
-        escape_json(&*url, &mut buf);
-        line.extend_from_slice(&buf);
-
-        line.extend_from_slice(b"\", \"Title\": \"");
-        escape_json(title, &mut buf);
-        line.extend_from_slice(&buf);
-
-        line.extend_from_slice(b"\", \"Links\": ");
-        line.extend_from_slice(links.to_string().as_bytes());
-
-        if let Some(t) = text {
-            line.extend_from_slice(b", \"Text\": \"");
-            escape_json(t, &mut buf);
-            line.extend_from_slice(&buf);
-            line.extend_from_slice(b"\"}\n");
-        } else if let Some(c) = content {
-            line.extend_from_slice(b", \"Content\": \"");
-            escape_json(c, &mut buf);
-            line.extend_from_slice(&buf);
-            line.extend_from_slice(b"\"}\n");
+    init_writer().await?.lock().await.finish(false).await?;
+
+    if let Some(errors) = init_errors().await?
+        && let Some(file) = errors.lock().await.take()
+    {
+        file.finish(false, 256).await?;
+    }
+
+    Ok(())
+}
+
+/// Writes the record of a failed page to `--errors` as NDJSON, a no-op without it
+pub async fn write_error(record: ErrorRecord) -> Res<()> {
+    let Some(errors) = init_errors().await? else {
+        return Ok(());
+    };
+
+    let mut line = Vec::with_capacity(512);
+    let mut ser = serde_json::Serializer::with_formatter(&mut line, SpacedFormatter);
+    Entries(record.entries())
+        .serialize(&mut ser)
+        .map_err(Box::new)
+        .context("Failed to serialize error record")?;
+    line.push(b'\n');
+
+    match errors.lock().await.as_mut() {
+        Some(file) => file.write_all(&line).await,
+        None => Ok(()),
+    }
+}
+
+/// `crawl.ndjson.gz` for the first file, then `crawl-00001.ndjson.gz`, `crawl-00002.ndjson.gz`,
+/// ..., the number goes before the format and compression extensions
+pub fn numbered_path(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let (rest, compression) = [".gz", ".zst"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext).map(|rest| (rest, *ext)))
+        .unwrap_or((&name, ""));
+    let (stem, ext) = rest.split_at(rest.rfind('.').filter(|i| *i > 0).unwrap_or(rest.len()));
+
+    path.with_file_name(format!("{}-{:05}{}{}", stem, index, ext, compression))
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+
+    PathBuf::from(part)
+}
+
+/// Keys `Record` writes itself, `--field` names can't reuse them
+pub const RECORD_KEYS: [&str; 19] = [
+    "URL",
+    "Title",
+    "Links",
+    "Status",
+    "FinalURL",
+    "Depth",
+    "Referrer",
+    "ContentType",
+    "ContentLength",
+    "ResponseTimeMs",
+    "FetchedAt",
+    "Lastmod",
+    "Priority",
+    "Proxy",
+    "Aliases",
+    "DuplicateOf",
+    "Meta",
+    "Text",
+    "Content",
+];
+
+/// Output record of a crawled page
+pub struct Record {
+    pub entry: FrontierEntry,
+    pub title: String,
+    /// Number of links queued from the page
+    pub links: usize,
+    pub text: Option<String>,
+    pub content: Option<String>,
+    /// Proxy (without credentials) that served the page
+    pub proxy: Option<String>,
+    /// Other URLs the page was found under, see `Canonicals`
+    pub aliases: Vec<String>,
+    /// Earlier page whose text this page's text nearly matches
+    pub duplicate_of: Option<String>,
+    pub fields: Vec<(String, FieldValue)>,
+    pub meta: Option<PageMeta>,
+    pub fetch: FetchInfo,
+}
+
+/// A record value, each format decides how to write it
+enum Value<'a> {
+    Null,
+    Str(&'a str),
+    Int(usize),
+    Float(f32),
+    List(&'a [String]),
+    Map(Vec<(&'a str, Value<'a>)>),
+}
+
+impl Record {
+    /// The record's keys and values in output order, optional keys without a value are left out
+    /// unless `all` is set since tabular formats need the same columns in every row, `select`
+    /// (`--fields`) picks keys case-insensitively and in its order
+    fn entries(&self, all: bool, select: Option<&[String]>) -> Vec<(Cow<'_, str>, Value<'_>)> {
+        let fetch = &self.fetch;
+        let mut entries = vec![
+            (Cow::from("URL"), Value::Str(&self.entry.url)),
+            (Cow::from("Title"), Value::Str(&self.title)),
+            (Cow::from("Links"), Value::Int(self.links)),
+            (Cow::from("Status"), Value::Int(fetch.status as usize)),
+            (Cow::from("FinalURL"), Value::Str(&fetch.final_url)),
+            (Cow::from("Depth"), Value::Int(self.entry.depth as usize)),
+            (Cow::from("Referrer"), Value::text(&self.entry.parent)),
+            (Cow::from("ContentType"), Value::text(&fetch.content_type)),
+            (Cow::from("ContentLength"), Value::Int(fetch.content_length)),
+            (
+                Cow::from("ResponseTimeMs"),
+                Value::Int(fetch.response_time.as_millis() as usize),
+            ),
+            (Cow::from("FetchedAt"), Value::Str(&fetch.fetched_at)),
+        ];
+
+        let optional = [
+            ("Lastmod", self.entry.lastmod.as_deref().map(Value::Str)),
+            ("Priority", self.entry.priority.map(Value::Float)),
+            ("Proxy", self.proxy.as_deref().map(Value::Str)),
+            (
+                "Aliases",
+                (!self.aliases.is_empty()).then_some(Value::List(&self.aliases)),
+            ),
+        ];
+
+        for (key, value) in optional {
+            match value {
+                Some(value) => entries.push((Cow::from(key), value)),
+                None if all => entries.push((Cow::from(key), Value::Null)),
+                None => {}
+            }
+        }
+
+        entries.push((Cow::from("DuplicateOf"), Value::text(&self.duplicate_of)));
+
+        if let Some(meta) = &self.meta {
+            entries.push((
+                Cow::from("Meta"),
+                Value::Map(vec![
+                    ("Description", Value::text(&meta.description)),
+                    ("Robots", Value::text(&meta.robots)),
+                    ("Canonical", Value::text(&meta.canonical)),
+                    ("Lang", Value::text(&meta.lang)),
+                    ("OpenGraph", Value::pairs(&meta.open_graph)),
+                    ("Twitter", Value::pairs(&meta.twitter)),
+                    ("Hreflang", Value::pairs(&meta.hreflang)),
+                ]),
+            ));
+        }
+
+        for (name, value) in &self.fields {
+            let value = match value {
+                FieldValue::First(Some(value)) => Value::Str(value),
+                FieldValue::First(None) => Value::Null,
+                FieldValue::All(values) => Value::List(values),
+            };
+
+            entries.push((Cow::from(name.as_str()), value));
+        }
+
+        if let Some(text) = &self.text {
+            entries.push((Cow::from("Text"), Value::Str(text)));
+        } else if let Some(content) = &self.content {
+            entries.push((Cow::from("Content"), Value::Str(content)));
+        }
+
+        let Some(select) = select else {
+            return entries;
+        };
+
+        let mut selected = Vec::with_capacity(select.len());
+        for key in select {
+            match entries
+                .iter()
+                .position(|(name, _)| name.eq_ignore_ascii_case(key))
+            {
+                Some(i) => selected.push(entries.remove(i)),
+                None if all => selected.push((Cow::from(key.clone()), Value::Null)),
+                None => {}
+            }
+        }
+
+        selected
+    }
+}
+
+/// Output record of a page that failed, see `write_error`
+pub struct ErrorRecord {
+    pub entry: FrontierEntry,
+    pub error: String,
+    pub kind: &'static str,
+    /// HTTP status, if the server responded
+    pub status: Option<u16>,
+}
+
+impl ErrorRecord {
+    pub fn new(entry: FrontierEntry, err: &ResErr) -> Self {
+        Self {
+            entry,
+            error: err.to_string(),
+            kind: err.kind(),
+            status: err.status(),
+        }
+    }
+
+    fn entries(&self) -> Vec<(Cow<'_, str>, Value<'_>)> {
+        vec![
+            (Cow::from("URL"), Value::Str(&self.entry.url)),
+            (Cow::from("Error"), Value::Str(&self.error)),
+            (Cow::from("ErrorKind"), Value::Str(self.kind)),
+            (
+                Cow::from("Status"),
+                self.status
+                    .map_or(Value::Null, |status| Value::Int(status as usize)),
+            ),
+            (Cow::from("Referrer"), Value::text(&self.entry.parent)),
+            (Cow::from("Depth"), Value::Int(self.entry.depth as usize)),
+        ]
+    }
+}
+
+/// A record's entries, written as a JSON object
+struct Entries<'a>(Vec<(Cow<'a, str>, Value<'a>)>);
+
+impl Serialize for Entries<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+
+        map.end()
+    }
+}
+
+impl Serialize for Value<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Null => serializer.serialize_none(),
+            Self::Str(s) => serializer.serialize_str(s),
+            Self::Int(n) => serializer.serialize_u64(*n as u64),
+            Self::Float(n) => serializer.serialize_f32(*n),
+            Self::List(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values.iter() {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Self::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    fn text(value: &'a Option<String>) -> Self {
+        value.as_deref().map_or(Self::Null, Self::Str)
+    }
+
+    fn pairs(pairs: &'a [(String, String)]) -> Self {
+        Self::Map(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.as_str(), Self::Str(value)))
+                .collect(),
+        )
+    }
+
+    /// Cell of a tabular format, lists and maps are written as JSON
+    fn to_cell(&self) -> Res<String> {
+        Ok(match self {
+            Self::Null => String::new(),
+            Self::Str(s) => s.to_string(),
+            Self::Int(n) => n.to_string(),
+            Self::Float(n) => n.to_string(),
+            Self::List(_) | Self::Map(_) => serde_json::to_string(self)
+                .map_err(Box::new)
+                .context("Failed to serialize output cell")?,
+        })
+    }
+}
+
+/// Compact JSON with a space after every `,` and `:`, the layout crawn always wrote
+struct SpacedFormatter;
+
+impl Formatter for SpacedFormatter {
+    fn begin_array_value<W: ?Sized + std::io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        CompactFormatter.begin_array_value(writer, first)?;
+
+        if first {
+            Ok(())
         } else {
-            line.extend_from_slice(b"}\n");
+            writer.write_all(b" ")
         }
+    }
 
-        line
-    })
-    .await
-    .context("Failed to escape output concurrently")?;
+    fn begin_object_key<W: ?Sized + std::io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        CompactFormatter.begin_object_key(writer, first)?;
 
-    init_writer()
-        .await
-        .lock()
-        .await
-        .write_all(&line)
-        .await
-        .context("Failed to write output entry")?;
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b" ")
+        }
+    }
 
-    Ok(())
+    fn begin_object_value<W: ?Sized + std::io::Write>(
+        &mut self,
+        writer: &mut W,
+    ) -> std::io::Result<()> {
+        writer.write_all(b": ")
+    }
 }
 
-#[inline(always)]
-fn escape_json<S: AsRef<str>>(s: S, buf: &mut Vec<u8>) {
-    buf.clear();
-
-    for byte in s.as_ref().bytes() {
-        match byte {
-            b'"' => buf.extend_from_slice(b"\\\""),
-            b'\\' => buf.extend_from_slice(b"\\\\"),
-            b'\n' => buf.extend_from_slice(b"\\n"),
-            b'\r' => buf.extend_from_slice(b"\\r"),
-            b'\t' => buf.extend_from_slice(b"\\t"),
-            b'\x08' => buf.extend_from_slice(b"\\b"),
-            b'\x0C' => buf.extend_from_slice(b"\\f"),
-            b if b < 0x20 => {
-                // Control characters: \u00XX
-                buf.extend_from_slice(b"\\u00");
-                buf.push(b"0123456789abcdef"[(b >> 4) as usize]);
-                buf.push(b"0123456789abcdef"[(b & 0x0F) as usize]);
+fn csv_row<I: IntoIterator<Item = T>, T: AsRef<str>>(cells: I) -> Res<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer
+        .write_record(cells.into_iter().map(|cell| cell.as_ref().to_owned()))
+        .map_err(Box::new)
+        .context("Failed to write CSV row")?;
+
+    writer
+        .into_inner()
+        .map_err(|err| Box::new(csv::Error::from(err.into_error())))
+        .context("Failed to write CSV row")
+}
+
+/// Tabs, newlines and backslashes in cells are escaped as `\t`, `\n`, `\r` and `\\`
+fn tsv_row<I: IntoIterator<Item = T>, T: AsRef<str>>(cells: I) -> Vec<u8> {
+    let mut row = Vec::new();
+
+    for (i, cell) in cells.into_iter().enumerate() {
+        if i > 0 {
+            row.push(b'\t');
+        }
+
+        for byte in cell.as_ref().bytes() {
+            match byte {
+                b'\t' => row.extend_from_slice(b"\\t"),
+                b'\n' => row.extend_from_slice(b"\\n"),
+                b'\r' => row.extend_from_slice(b"\\r"),
+                b'\\' => row.extend_from_slice(b"\\\\"),
+                b => row.push(b),
             }
-            b => buf.push(b),
         }
     }
+
+    row.push(b'\n');
+    row
+}
+
+/// Pipes in cells are escaped and line breaks become `<br>`
+fn markdown_row<I: IntoIterator<Item = T>, T: AsRef<str>>(cells: I) -> Vec<u8> {
+    let mut row = Vec::from(b"|");
+
+    for cell in cells {
+        let cell = cell
+            .as_ref()
+            .lines()
+            .map(|line| line.trim().replace('|', "\\|"))
+            .collect::<Vec<String>>()
+            .join("<br>");
+
+        row.push(b' ');
+        row.extend_from_slice(cell.as_bytes());
+        row.extend_from_slice(b" |");
+    }
+
+    row.push(b'\n');
+    row
+}
+
+pub async fn write_output(record: Record) -> Res<()> {
+    let format = crate::ARGS.format.unwrap_or_default();
+    let select = crate::ARGS.select_fields.as_deref();
+
+    let (record, line) = tokio::task::spawn_blocking(move || {
+        let line = format.record(&record, select);
+        (record, line)
+    })
+    .await
+    .context("Failed to serialize output concurrently")?;
+    let line = line?;
+
+    let mut output = init_writer().await?.lock().await;
+
+    if output.should_rotate() {
+        output.finish(true).await?;
+    }
+
+    // Every file gets its own header, so each one can be read on its own
+    if output.records == 0 {
+        let header = format.header(&record, select)?;
+
+        output
+            .write_all(&header)
+            .await
+            .context("Failed to write output header")?;
+    }
+
+    let separator = format.separator(output.records == 0);
+    output.records += 1;
+
+    output.write_all(separator).await?;
+    output.write_all(&line).await
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::output::escape_json;
+    use std::{io::Read, path::Path, time::Duration};
 
-    #[tokio::test]
-    async fn test_escaping() {
-        let mut buf = Vec::new();
+    use flate2::read::GzDecoder;
+    use serde::Serialize;
+
+    use crate::{
+        FrontierEntry,
+        crawler::FieldValue,
+        error::{Res, ResErr, ResExt},
+        fetch::FetchInfo,
+        output::{
+            Entries, ErrorRecord, OutputFile, OutputFormat, Record, SpacedFormatter, numbered_path,
+            part_path,
+        },
+    };
+
+    fn record(title: &str) -> Record {
+        Record {
+            entry: FrontierEntry::from(String::from("https://example.com/"))
+                .child(String::from("https://example.com/a")),
+            title: title.to_owned(),
+            links: 3,
+            text: None,
+            content: None,
+            proxy: None,
+            aliases: vec![String::from("https://example.com/b")],
+            duplicate_of: None,
+            fields: vec![(String::from("price"), FieldValue::First(None))],
+            meta: None,
+            fetch: FetchInfo {
+                status: 200,
+                final_url: String::from("https://example.com/a/"),
+                content_type: Some(String::from("text/html; charset=utf-8")),
+                content_length: 5120,
+                response_time: Duration::from_millis(84),
+                fetched_at: String::from("2026-01-02T03:04:05Z"),
+            },
+        }
+    }
 
+    #[test]
+    fn test_escaping() -> Res<()> {
         let s = "escape\t string\r\nfor \x08 \\ testing \x0C\"escape\" function";
 
-        escape_json(s, &mut buf);
+        let line = OutputFormat::Ndjson.record(&record(s), None)?;
+
+        assert_eq!(
+            String::from_utf8_lossy(&line),
+            "{\"URL\": \"https://example.com/a\", \"Title\": \"escape\\t string\\r\\nfor \\b \\\\ \
+             testing \\f\\\"escape\\\" function\", \"Links\": 3, \"Status\": 200, \"FinalURL\": \
+             \"https://example.com/a/\", \"Depth\": 1, \"Referrer\": \"https://example.com/\", \
+             \"ContentType\": \"text/html; charset=utf-8\", \"ContentLength\": 5120, \
+             \"ResponseTimeMs\": 84, \"FetchedAt\": \"2026-01-02T03:04:05Z\", \"Aliases\": \
+             [\"https://example.com/b\"], \"DuplicateOf\": null, \"price\": null}\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_formats() -> Res<()> {
+        let record = record("Caf\u{e9} | \"menu\"\ttoday");
+        let select: Vec<String> = ["url", "Title", "Status", "Aliases", "Lastmod", "price"]
+            .map(String::from)
+            .to_vec();
+        let write = |format: OutputFormat| -> Res<String> {
+            let mut out = format.header(&record, Some(&select))?;
+            out.extend_from_slice(format.separator(true));
+            out.extend(format.record(&record, Some(&select))?);
+            out.extend_from_slice(format.footer(1));
+
+            Ok(String::from_utf8_lossy(&out).into_owned())
+        };
 
         assert_eq!(
-            &buf,
-            b"escape\\t string\\r\\nfor \\b \\\\ testing \\f\\\"escape\\\" function"
+            write(OutputFormat::Json)?,
+            "[\n  {\"URL\": \"https://example.com/a\", \"Title\": \"Caf\u{e9} | \\\"menu\\\"\\ttoday\", \
+             \"Status\": 200, \"Aliases\": [\"https://example.com/b\"], \"price\": null}\n]\n"
         );
+        assert_eq!(
+            write(OutputFormat::Csv)?,
+            "URL,Title,Status,Aliases,Lastmod,price\n\
+             https://example.com/a,\"Caf\u{e9} | \"\"menu\"\"\ttoday\",200,\
+             \"[\"\"https://example.com/b\"\"]\",,\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Tsv)?,
+            "URL\tTitle\tStatus\tAliases\tLastmod\tprice\n\
+             https://example.com/a\tCaf\u{e9} | \"menu\"\\ttoday\t200\t\
+             [\"https://example.com/b\"]\t\t\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Markdown)?,
+            "| URL | Title | Status | Aliases | Lastmod | price |\n\
+             | --- | --- | --- | --- | --- | --- |\n\
+             | https://example.com/a | Caf\u{e9} \\| \"menu\"\ttoday | 200 | \
+             [\"https://example.com/b\"] |  |  |\n"
+        );
+        assert_eq!(OutputFormat::Json.footer(0), b"[]\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_records() -> Res<()> {
+        let err = ResErr::new(
+            "Failed to fetch URL: https://example.com/a",
+            std::io::Error::other("connection reset"),
+        );
+        let record = ErrorRecord::new(record("").entry, &err);
+
+        let mut line = Vec::new();
+        let mut ser = serde_json::Serializer::with_formatter(&mut line, SpacedFormatter);
+        Entries(record.entries())
+            .serialize(&mut ser)
+            .map_err(Box::new)
+            .context("Failed to serialize error record")?;
+
+        assert_eq!(
+            String::from_utf8_lossy(&line),
+            format!(
+                "{{\"URL\": \"https://example.com/a\", \"Error\": {}, \"ErrorKind\": \"Io\", \
+                 \"Status\": null, \"Referrer\": \"https://example.com/\", \"Depth\": 1}}",
+                serde_json::to_string(&err.to_string()).map_err(Box::new)?
+            )
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_output_files() -> Res<()> {
+        assert_eq!(
+            numbered_path(Path::new("out/crawl.ndjson.gz"), 0),
+            Path::new("out/crawl.ndjson.gz")
+        );
+        assert_eq!(
+            numbered_path(Path::new("out/crawl.ndjson.gz"), 12),
+            Path::new("out/crawl-00012.ndjson.gz")
+        );
+        assert_eq!(
+            numbered_path(Path::new("docs.warc.gz"), 1),
+            Path::new("docs-00001.warc.gz")
+        );
+        assert_eq!(
+            numbered_path(Path::new("my.site.csv"), 1),
+            Path::new("my.site-00001.csv")
+        );
+        assert_eq!(numbered_path(Path::new(".out"), 1), Path::new(".out-00001"));
+
+        let dir = std::env::temp_dir().join(format!("crawn-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).context("Failed to create temp dir")?;
+        let path = dir.join("crawl.ndjson.gz");
+
+        let mut first = OutputFile::create(path.clone(), 0, 256).await?;
+        first
+            .write_all(b"{\"URL\": \"https://example.com/a\"}\n")
+            .await?;
+
+        // Nothing has the final name until the file is finished
+        assert!(!path.exists());
+        assert!(part_path(&path).exists());
+
+        let second = first.finish(true, 256).await?;
+        let mut decoded = String::new();
+        GzDecoder::new(std::fs::File::open(&path).context("Failed to open output file")?)
+            .read_to_string(&mut decoded)
+            .context("Failed to decompress output file")?;
+
+        assert_eq!(decoded, "{\"URL\": \"https://example.com/a\"}\n");
+        assert!(!part_path(&path).exists());
+        assert!(part_path(&dir.join("crawl-00001.ndjson.gz")).exists());
+
+        if let Some(second) = second {
+            second.finish(false, 256).await?;
+        }
+        assert!(dir.join("crawl-00001.ndjson.gz").exists());
+
+        // A crashed crawl's `.part` file is kept: resuming continues in the next free file and
+        // a fresh crawl refuses to overwrite it
+        let crashed = dir.join("crashed.ndjson");
+        std::fs::write(
+            part_path(&crashed),
+            "{\"URL\": \"https://example.com/a\"}\n",
+        )
+        .context("Failed to write output file")?;
+
+        assert!(OutputFile::open(crashed.clone(), false, 256).await.is_err());
+
+        let resumed = OutputFile::open(crashed.clone(), true, 256).await?;
+        assert_eq!(resumed.index, 1);
+        resumed.finish(false, 256).await?;
+
+        assert_eq!(
+            std::fs::read_to_string(part_path(&crashed)).context("Failed to read output file")?,
+            "{\"URL\": \"https://example.com/a\"}\n"
+        );
+        assert!(dir.join("crashed-00001.ndjson").exists());
+
+        std::fs::remove_dir_all(&dir).context("Failed to remove temp dir")?;
+
+        Ok(())
     }
 }
@@ -1,60 +1,508 @@
-use crate::error::Res;
-use std::collections::{HashSet, VecDeque};
+use crate::error::{Res, ResExt};
+use resext::ctx;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
 use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
 
-pub trait UrlRepo {
-    fn add(&mut self, url: String) -> impl Future<Output = Res<()>> + Send + Sync;
+/// URL waiting in the frontier along with the metadata it was discovered with
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrontierEntry {
+    pub url: String,
+    /// Number of links followed from the base URL (0 for the base URL itself)
+    pub depth: u8,
+    /// Page the URL was found on (`None` for the base URL and sitemap URLs)
+    pub parent: Option<String>,
+    /// `<lastmod>` from the sitemap that listed the URL
+    pub lastmod: Option<String>,
+    /// `<priority>` from the sitemap that listed the URL
+    pub priority: Option<f32>,
+    /// Text of the link the URL was found through
+    pub anchor: Option<String>,
+}
+
+impl From<String> for FrontierEntry {
+    fn from(url: String) -> Self {
+        Self {
+            url,
+            ..Default::default()
+        }
+    }
+}
+
+impl FrontierEntry {
+    /// Entry for a link found on this entry's page
+    pub fn child(&self, url: String) -> Self {
+        Self {
+            url,
+            depth: self.depth.saturating_add(1),
+            parent: Some(self.url.clone()),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the text of the link the entry was found through, empty text is dropped
+    pub fn with_anchor(self, anchor: String) -> Self {
+        Self {
+            anchor: Some(anchor).filter(|a| !a.is_empty()),
+            ..self
+        }
+    }
+
+    /// Tab-separated line used by `FileRepo` (URLs never contain raw tabs or newlines and
+    /// anchor text has its whitespace collapsed)
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.url,
+            self.depth,
+            self.parent.as_deref().unwrap_or_default(),
+            self.lastmod.as_deref().unwrap_or_default(),
+            self.priority.map(|p| p.to_string()).unwrap_or_default(),
+            self.anchor.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn from_line(line: &str) -> Self {
+        let mut fields = line.split('\t');
+
+        Self {
+            url: fields.next().unwrap_or_default().to_owned(),
+            depth: fields
+                .next()
+                .and_then(|f| f.parse().ok())
+                .unwrap_or_default(),
+            parent: fields.next().filter(|f| !f.is_empty()).map(String::from),
+            lastmod: fields.next().filter(|f| !f.is_empty()).map(String::from),
+            priority: fields.next().and_then(|f| f.parse().ok()),
+            anchor: fields.next().filter(|f| !f.is_empty()).map(String::from),
+        }
+    }
+}
+
+/// Order in which frontier URLs are crawled
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum Strategy {
+    /// Breadth-first, in discovery order
+    #[default]
+    Bfs,
+    /// Depth-first, most recently discovered first
+    Dfs,
+    /// Highest score first, ties in discovery order
+    BestFirst,
+}
+
+/// Scores a frontier entry for `Strategy::BestFirst`, higher is crawled first
+pub type Scorer = Arc<dyn Fn(&FrontierEntry) -> f32 + Send + Sync>;
 
-    fn pop(&mut self) -> impl Future<Output = Res<Option<String>>> + Send + Sync;
+struct Scored {
+    score: f32,
+    seq: u64,
+    entry: FrontierEntry,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
 
-    fn kick(&mut self, url: String) -> impl Future<Output = Res<()>> + Send + Sync;
+pub trait UrlRepo {
+    fn add(&mut self, entry: FrontierEntry) -> impl Future<Output = Res<()>> + Send + Sync;
+
+    fn pop(&mut self) -> impl Future<Output = Res<Option<FrontierEntry>>> + Send + Sync;
 
     fn mark(&mut self, url: String) -> impl Future<Output = Res<()>> + Send + Sync;
+
+    /// Adds a URL to the visited set without queueing it, returns false if it was already seen
+    fn mark_seen(&mut self, url: String) -> impl Future<Output = Res<bool>> + Send + Sync;
+
+    /// Takes every change since the last call, they are made durable by `Checkpoint::save` once
+    /// the repo lock has been released (nothing to save for in-memory repos)
+    fn checkpoint(&mut self) -> Checkpoint;
+
+    /// Changes the crawl order, URLs already in the frontier are reordered
+    fn order_by(&mut self, strategy: Strategy, scorer: Scorer);
 }
 
 #[derive(Default)]
 pub struct InMemoryRepo {
-    urls: VecDeque<String>,
+    urls: VecDeque<FrontierEntry>,
+    scored: BinaryHeap<Scored>,
+    seq: u64,
+    strategy: Strategy,
+    scorer: Option<Scorer>,
     visited: HashSet<String>,
 }
 
+impl InMemoryRepo {
+    fn enqueue(&mut self, entry: FrontierEntry) {
+        match (&self.strategy, &self.scorer) {
+            (Strategy::BestFirst, Some(scorer)) => {
+                self.seq += 1;
+                self.scored.push(Scored {
+                    score: scorer(&entry),
+                    seq: self.seq,
+                    entry,
+                });
+            }
+            _ => self.urls.push_back(entry),
+        }
+    }
+
+    fn dequeue(&mut self) -> Option<FrontierEntry> {
+        match self.strategy {
+            Strategy::Dfs => self.urls.pop_back(),
+            _ => self
+                .scored
+                .pop()
+                .map(|scored| scored.entry)
+                .or_else(|| self.urls.pop_front()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.urls.is_empty() && self.scored.is_empty()
+    }
+}
+
 impl UrlRepo for InMemoryRepo {
-    async fn add(&mut self, url: String) -> Res<()> {
+    async fn add(&mut self, entry: FrontierEntry) -> Res<()> {
         let vis = &mut self.visited;
 
-        if vis.contains(&url) || url.is_empty() {
+        if vis.contains(&entry.url) || entry.url.is_empty() {
             Ok(())
         } else {
-            if &url != "M" {
-                vis.insert(url.clone());
-            }
-            self.urls.push_back(url);
+            vis.insert(entry.url.clone());
+            self.enqueue(entry);
 
             Ok(())
         }
     }
 
-    async fn pop(&mut self) -> Res<Option<String>> {
-        Ok(self.urls.pop_front())
+    async fn pop(&mut self) -> Res<Option<FrontierEntry>> {
+        Ok(self.dequeue())
     }
 
-    async fn kick(&mut self, url: String) -> Res<()> {
-        self.urls.push_front(url);
+    async fn mark(&mut self, url: String) -> Res<()> {
+        self.visited.insert(url);
 
         Ok(())
     }
 
-    async fn mark(&mut self, url: String) -> Res<()> {
-        self.visited.insert(url);
+    async fn mark_seen(&mut self, url: String) -> Res<bool> {
+        Ok(!url.is_empty() && self.visited.insert(url))
+    }
+
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::default()
+    }
+
+    fn order_by(&mut self, strategy: Strategy, scorer: Scorer) {
+        let entries: Vec<FrontierEntry> = std::mem::take(&mut self.urls)
+            .into_iter()
+            .chain(
+                std::mem::take(&mut self.scored)
+                    .into_sorted_vec()
+                    .into_iter()
+                    .rev()
+                    .map(|scored| scored.entry),
+            )
+            .collect();
+
+        self.strategy = strategy;
+        self.scorer = Some(scorer);
+
+        for entry in entries {
+            self.enqueue(entry);
+        }
+    }
+}
+
+/// Changes taken from a repo by `UrlRepo::checkpoint`, appended to its state files by `save`
+#[derive(Default)]
+pub struct Checkpoint {
+    log: Option<Arc<Mutex<StateLog>>>,
+    frontier: Vec<u8>,
+    visited: Vec<u8>,
+}
+
+impl Checkpoint {
+    pub async fn save(self) -> Res<()> {
+        let Some(log) = self.log else {
+            return Ok(());
+        };
+
+        let mut log = log.lock().await;
+
+        if !self.frontier.is_empty() {
+            log.frontier.append(&self.frontier).await?;
+        }
+        if !self.visited.is_empty() {
+            log.visited.append(&self.visited).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct StateLog {
+    frontier: StateFile,
+    visited: StateFile,
+}
+
+struct StateFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl StateFile {
+    async fn open(path: PathBuf) -> Res<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .context(ctx!(
+                "Failed to open state file: {}",
+                path.to_string_lossy()
+            ))?;
+
+        Ok(Self { path, file })
+    }
+
+    async fn append(&mut self, buf: &[u8]) -> Res<()> {
+        self.file.write_all(buf).await.context(ctx!(
+            "Failed to write state file: {}",
+            self.path.to_string_lossy()
+        ))?;
+
+        self.file.sync_data().await.context(ctx!(
+            "Failed to sync state file: {}",
+            self.path.to_string_lossy()
+        ))
+    }
+}
+
+/// File-backed repo that keeps its state in a directory so a crawl can be resumed later
+///
+/// Layout of the state directory:
+/// - `seed`: the base URL of the crawl
+/// - `frontier`: log of URLs added to the frontier (`+`, with their depth, parent and sitemap
+///   metadata) and URLs done crawling (`-`), one per line (append-only, compacted on open)
+/// - `visited`: every URL seen so far, one per line (append-only)
+pub struct FileRepo {
+    dir: PathBuf,
+    inner: InMemoryRepo,
+    log: Option<Arc<Mutex<StateLog>>>,
+    frontier: Vec<u8>,
+    visited: Vec<u8>,
+    seed: Option<String>,
+}
+
+impl FileRepo {
+    /// Opens the state directory, loading the previous state if `resume` is set or
+    /// starting from a clean state otherwise
+    pub async fn open(dir: PathBuf, resume: bool) -> Res<Self> {
+        fs::create_dir_all(&dir).await.context(ctx!(
+            "Failed to create state directory: {}",
+            dir.to_string_lossy()
+        ))?;
+
+        let mut repo = Self {
+            dir,
+            inner: InMemoryRepo::default(),
+            log: None,
+            frontier: Vec::new(),
+            visited: Vec::new(),
+            seed: None,
+        };
+
+        if resume {
+            repo.seed = read_lines(&repo.dir.join("seed")).await?.pop();
+
+            // URLs that were added but never done (including ones that were still being crawled)
+            // go back into the frontier, which is rewritten with only those
+            let lines = read_lines(&repo.dir.join("frontier")).await?;
+            let done: HashSet<&str> = lines
+                .iter()
+                .filter_map(|line| line.strip_prefix("-\t"))
+                .collect();
+
+            let mut buf = Vec::new();
+            for entry in lines
+                .iter()
+                .filter_map(|line| line.strip_prefix("+\t"))
+                .map(FrontierEntry::from_line)
+                .filter(|entry| !done.contains(entry.url.as_str()))
+            {
+                push_line(&mut buf, "+", &entry.to_line());
+                repo.inner.enqueue(entry);
+            }
+
+            write_atomic(&repo.dir.join("frontier"), &buf).await?;
+
+            repo.inner.visited = read_lines(&repo.dir.join("visited"))
+                .await?
+                .into_iter()
+                .collect();
+        } else {
+            for name in ["seed", "frontier", "visited"] {
+                write_atomic(&repo.dir.join(name), b"").await?;
+            }
+        }
+
+        repo.log = Some(Arc::new(Mutex::new(StateLog {
+            frontier: StateFile::open(repo.dir.join("frontier")).await?,
+            visited: StateFile::open(repo.dir.join("visited")).await?,
+        })));
+
+        Ok(repo)
+    }
+
+    /// Base URL of the crawl that was saved in the state directory
+    pub fn seed(&self) -> Option<&str> {
+        self.seed.as_deref()
+    }
+
+    pub async fn set_seed(&mut self, url: &str) -> Res<()> {
+        write_atomic(&self.dir.join("seed"), url.as_bytes()).await?;
+        self.seed = Some(url.to_owned());
 
         Ok(())
     }
+
+    /// Returns true if there are URLs left to crawl from a previous run
+    pub fn has_pending(&self) -> bool {
+        !self.inner.is_empty()
+    }
+}
+
+impl UrlRepo for FileRepo {
+    async fn add(&mut self, entry: FrontierEntry) -> Res<()> {
+        if !self.inner.visited.contains(&entry.url) && !entry.url.is_empty() {
+            push_line(&mut self.frontier, "+", &entry.to_line());
+            push_line(&mut self.visited, "", &entry.url);
+        }
+
+        self.inner.add(entry).await
+    }
+
+    async fn pop(&mut self) -> Res<Option<FrontierEntry>> {
+        self.inner.pop().await
+    }
+
+    async fn mark(&mut self, url: String) -> Res<()> {
+        push_line(&mut self.frontier, "-", &url);
+
+        if !self.inner.visited.contains(&url) {
+            push_line(&mut self.visited, "", &url);
+        }
+
+        self.inner.mark(url).await
+    }
+
+    async fn mark_seen(&mut self, url: String) -> Res<bool> {
+        if self.inner.visited.contains(&url) || url.is_empty() {
+            return Ok(false);
+        }
+
+        push_line(&mut self.visited, "", &url);
+        self.inner.mark_seen(url).await
+    }
+
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint {
+            log: self.log.clone(),
+            frontier: std::mem::take(&mut self.frontier),
+            visited: std::mem::take(&mut self.visited),
+        }
+    }
+
+    fn order_by(&mut self, strategy: Strategy, scorer: Scorer) {
+        self.inner.order_by(strategy, scorer);
+    }
+}
+
+/// Appends a state file line, `tag` is separated from `line` by a tab unless it's empty
+fn push_line(buf: &mut Vec<u8>, tag: &str, line: &str) {
+    if !tag.is_empty() {
+        buf.extend_from_slice(tag.as_bytes());
+        buf.push(b'\t');
+    }
+    buf.extend_from_slice(line.as_bytes());
+    buf.push(b'\n');
+}
+
+async fn read_lines(path: &Path) -> Res<Vec<String>> {
+    match fs::read_to_string(path).await {
+        Ok(content) => Ok(content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()),
+
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+
+        Err(e) => Err(e).context(ctx!(
+            "Failed to read state file: {}",
+            path.to_string_lossy()
+        )),
+    }
+}
+
+/// Writes to a temporary file and renames it over `path` so a crash never leaves a partial file
+async fn write_atomic(path: &Path, content: &[u8]) -> Res<()> {
+    let tmp = path.with_extension("tmp");
+
+    let mut file = File::create(&tmp).await.context(ctx!(
+        "Failed to create state file: {}",
+        tmp.to_string_lossy()
+    ))?;
+
+    file.write_all(content).await.context(ctx!(
+        "Failed to write state file: {}",
+        tmp.to_string_lossy()
+    ))?;
+
+    file.sync_all()
+        .await
+        .context(ctx!("Failed to sync state file: {}", tmp.to_string_lossy()))?;
+
+    fs::rename(&tmp, path).await.context(ctx!(
+        "Failed to replace state file: {}",
+        path.to_string_lossy()
+    ))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use crate::{
-        InMemoryRepo, UrlRepo,
+        FileRepo, FrontierEntry, InMemoryRepo, Strategy, UrlRepo,
         error::{Res, ResExt},
     };
 
@@ -63,15 +511,139 @@ mod tests {
         let mut repo = InMemoryRepo::default();
 
         for i in 0..50 {
-            repo.add(format!("https://example.com/index{}.html", i))
+            repo.add(format!("https://example.com/index{}.html", i).into())
                 .await
                 .context("Failed to add URL to repo")?;
         }
 
-        while let Some(url) = repo.pop().await.context("Failed to pop URL from repo")? {
-            println!("{}", url);
+        while let Some(entry) = repo.pop().await.context("Failed to pop URL from repo")? {
+            println!("{}", entry.url);
         }
 
+        assert!(
+            !repo
+                .mark_seen(String::from("https://example.com/index0.html"))
+                .await?
+        );
+        assert!(
+            repo.mark_seen(String::from("https://example.com/canonical.html"))
+                .await?
+        );
+        assert!(
+            !repo
+                .mark_seen(String::from("https://example.com/canonical.html"))
+                .await?
+        );
+
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_crawl_order() -> Res<()> {
+        let urls = |strategy: Strategy| async move {
+            let mut repo = InMemoryRepo::default();
+            repo.add(String::from("https://example.com/a").into())
+                .await?;
+            repo.order_by(
+                strategy,
+                Arc::new(|entry: &FrontierEntry| entry.priority.unwrap_or_default()),
+            );
+
+            for (url, priority) in [("b", 0.2), ("c", 0.9), ("d", 0.2)] {
+                repo.add(FrontierEntry {
+                    url: format!("https://example.com/{}", url),
+                    priority: Some(priority),
+                    ..Default::default()
+                })
+                .await?;
+            }
+
+            let mut urls = Vec::new();
+            while let Some(entry) = repo.pop().await? {
+                urls.push(
+                    entry
+                        .url
+                        .trim_start_matches("https://example.com/")
+                        .to_owned(),
+                );
+            }
+
+            Res::Ok(urls)
+        };
+
+        assert_eq!(urls(Strategy::Bfs).await?, vec!["a", "b", "c", "d"]);
+        assert_eq!(urls(Strategy::Dfs).await?, vec!["d", "c", "b", "a"]);
+        assert_eq!(urls(Strategy::BestFirst).await?, vec!["c", "b", "d", "a"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_filerepo_resume() -> Res<()> {
+        let dir = std::env::temp_dir().join(format!("crawn-test-{}", std::process::id()));
+
+        let base = FrontierEntry::from(String::from("https://example.com/"));
+        let sitemap_entry = FrontierEntry {
+            url: String::from("https://example.com/orphan.html"),
+            depth: 1,
+            lastmod: Some(String::from("2026-01-01")),
+            priority: Some(0.8),
+            ..Default::default()
+        };
+
+        {
+            let mut repo = FileRepo::open(dir.clone(), false).await?;
+            repo.set_seed(&base.url).await?;
+
+            for i in 0..3 {
+                repo.add(base.child(format!("https://example.com/index{}.html", i)))
+                    .await?;
+            }
+            repo.add(sitemap_entry.clone()).await?;
+
+            // Popped but never marked as done, so it must be crawled again after resuming
+            repo.pop().await?;
+            repo.checkpoint().save().await?;
+
+            // Done in a later checkpoint, which is saved first
+            let done = repo.pop().await?.map(|entry| entry.url).unwrap_or_default();
+            repo.mark(done).await?;
+            let later = repo.checkpoint();
+            repo.add(base.child(String::from("https://example.com/late.html")))
+                .await?;
+            repo.checkpoint().save().await?;
+            later.save().await?;
+        }
+
+        // Compacting on open must not lose anything
+        {
+            FileRepo::open(dir.clone(), true).await?;
+        }
+
+        let mut repo = FileRepo::open(dir.clone(), true).await?;
+
+        assert_eq!(repo.seed(), Some("https://example.com/"));
+
+        repo.add(base.child(String::from("https://example.com/index2.html")))
+            .await?;
+
+        let mut entries = Vec::new();
+        while let Some(entry) = repo.pop().await? {
+            entries.push(entry);
+        }
+
+        assert_eq!(
+            entries,
+            vec![
+                base.child(String::from("https://example.com/index0.html")),
+                base.child(String::from("https://example.com/index2.html")),
+                sitemap_entry,
+                base.child(String::from("https://example.com/late.html")),
+            ]
+        );
+
+        tokio::fs::remove_dir_all(&dir)
+            .await
+            .context("Failed to remove test state directory")
+    }
 }
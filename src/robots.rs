@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use url::Url;
+
+/// Product token matched against `User-agent` lines in robots.txt when none can be taken from
+/// the User-Agent
+const DEFAULT_AGENT_TOKEN: &str = "crawn";
+
+/// Extracts the product token (the name before the version) from a User-Agent, e.g. `mybot` from
+/// `MyBot/1.0 (+https://example.com/bot)`
+pub fn user_agent_token(user_agent: &str) -> String {
+    let token = user_agent
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .next()
+        .unwrap_or_default();
+
+    if token.is_empty() {
+        String::from(DEFAULT_AGENT_TOKEN)
+    } else {
+        token.to_ascii_lowercase()
+    }
+}
+
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+/// Rules from a host's robots.txt that apply to crawn
+#[derive(Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+    pub sitemaps: Vec<String>,
+    /// Why robots.txt couldn't be fetched, only set on `disallow_all` rules
+    pub error: Option<String>,
+}
+
+impl Robots {
+    /// Parses robots.txt content, keeping only the groups for `agent` (or `*` if none match it)
+    pub fn parse(content: &str, agent: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut sitemaps = Vec::new();
+        let mut in_agents = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push(Group::default());
+                        in_agents = true;
+                    }
+
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_ascii_lowercase());
+                    }
+                }
+
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+
+                    // An empty `Disallow:` allows everything, so it adds no rule
+                    if let Some(group) = groups.last_mut()
+                        && !value.is_empty()
+                    {
+                        group.rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_owned(),
+                        });
+                    }
+                }
+
+                "crawl-delay" => {
+                    in_agents = false;
+
+                    if let Some(group) = groups.last_mut() {
+                        group.crawl_delay = value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                            .map(Duration::from_secs_f64);
+                    }
+                }
+
+                "sitemap" if !value.is_empty() => sitemaps.push(value.to_owned()),
+
+                _ => {}
+            }
+        }
+
+        let agent = agent.to_ascii_lowercase();
+        let has_agent = groups.iter().any(|g| g.agents.contains(&agent));
+        let agent = if has_agent { agent } else { String::from("*") };
+
+        let mut robots = Self {
+            sitemaps,
+            ..Default::default()
+        };
+
+        for group in groups.into_iter().filter(|g| g.agents.contains(&agent)) {
+            robots.rules.extend(group.rules);
+            robots.crawl_delay = robots.crawl_delay.or(group.crawl_delay);
+        }
+
+        robots
+    }
+
+    /// Rules used when robots.txt can't be fetched because the host is unreachable or erroring
+    pub fn disallow_all(error: String) -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: String::from("/"),
+            }],
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    /// Checks the URL's path and query against the rules, the longest matching rule wins and
+    /// `Allow` wins ties
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let mut target = url.path().to_owned();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+
+        if target == "/robots.txt" {
+            return true;
+        }
+
+        self.rules
+            .iter()
+            .filter(|rule| matches_pattern(&rule.pattern, &target))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// Matches a robots.txt path pattern where `*` matches any sequence and a trailing `$` anchors
+/// the pattern to the end of the path
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return !anchored || rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    if anchored {
+        rest.ends_with(last)
+    } else {
+        rest.contains(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+
+    use crate::{
+        error::{Res, ResExt},
+        robots::{Robots, user_agent_token},
+    };
+
+    #[test]
+    fn test_robots_rules() -> Res<()> {
+        let robots = Robots::parse(
+            r#"
+# Example robots.txt
+User-agent: googlebot
+Disallow: /
+
+User-agent: *
+Crawl-delay: 1.5
+Disallow: /private/
+Allow: /private/public*.html$
+Disallow: /*?session=
+
+Sitemap: https://example.com/sitemap.xml
+            "#,
+            &user_agent_token("Crawn/0.3.0 (+https://github.com/Tahaa-Dev/crawn)"),
+        );
+
+        let allowed = |path: &str| -> Res<bool> {
+            let url = Url::parse(&format!("https://example.com{}", path))
+                .context("Failed to parse URL for testing")?;
+
+            Ok(robots.is_allowed(&url))
+        };
+
+        assert!(allowed("/docs/index.html")?);
+        assert!(!allowed("/private/notes.html")?);
+        assert!(allowed("/private/public-notes.html")?);
+        assert!(!allowed("/private/public-notes.html?x=1")?);
+        assert!(!allowed("/search?session=42")?);
+        assert!(allowed("/robots.txt")?);
+
+        assert_eq!(robots.crawl_delay, Some(Duration::from_millis(1500)));
+        assert_eq!(robots.sitemaps, vec!["https://example.com/sitemap.xml"]);
+
+        Ok(())
+    }
+}
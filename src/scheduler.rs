@@ -0,0 +1,124 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use tokio::sync::{Mutex, Notify};
+
+use crate::{FrontierEntry, UrlRepo, error::Res};
+
+/// Hands out frontier entries to workers, idle workers wait for a notification instead of
+/// polling and every worker is released once the frontier is empty with nothing in flight
+pub struct Scheduler<R> {
+    repo: Arc<Mutex<R>>,
+    in_flight: AtomicUsize,
+    notify: Notify,
+}
+
+/// Entry handed out to a worker, it counts as in flight until dropped
+pub struct Job<R> {
+    pub entry: FrontierEntry,
+    scheduler: Arc<Scheduler<R>>,
+}
+
+impl<R: UrlRepo> Scheduler<R> {
+    pub fn new(repo: Arc<Mutex<R>>) -> Arc<Self> {
+        Arc::new(Self {
+            repo,
+            in_flight: AtomicUsize::new(0),
+            notify: Notify::new(),
+        })
+    }
+
+    /// Waits for the next entry, returns `None` once the crawl is finished
+    pub async fn next(self: &Arc<Self>) -> Res<Option<Job<R>>> {
+        loop {
+            // Registered before checking the frontier so a job finishing in between isn't missed
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut repo = self.repo.lock().await;
+
+                if let Some(entry) = repo.pop().await? {
+                    self.in_flight.fetch_add(1, Ordering::SeqCst);
+
+                    return Ok(Some(Job {
+                        entry,
+                        scheduler: Arc::clone(self),
+                    }));
+                }
+
+                // Jobs are only counted while the repo is locked, so an empty frontier with no
+                // jobs in flight means no more URLs can be discovered
+                if self.in_flight.load(Ordering::SeqCst) == 0 {
+                    self.notify.notify_waiters();
+
+                    return Ok(None);
+                }
+            }
+
+            notified.await;
+        }
+    }
+}
+
+impl<R> Drop for Job<R> {
+    fn drop(&mut self) {
+        self.scheduler.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        // The job may have added new URLs or been the last one in flight, either way idle
+        // workers have to check the frontier again
+        self.scheduler.notify.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+
+    use crate::{
+        FrontierEntry, InMemoryRepo, UrlRepo,
+        error::{Res, ResExt},
+        scheduler::Scheduler,
+    };
+
+    #[tokio::test]
+    async fn test_idle_worker_wakes_up() -> Res<()> {
+        let repo = Arc::new(Mutex::new(InMemoryRepo::default()));
+        let base = FrontierEntry::from(String::from("https://example.com/"));
+        repo.lock().await.add(base.clone()).await?;
+
+        let scheduler = Scheduler::new(Arc::clone(&repo));
+        let job = scheduler.next().await?;
+
+        // The frontier is empty but a job is in flight, so this worker has to wait for it
+        let idle = tokio::spawn({
+            let scheduler = Arc::clone(&scheduler);
+            async move {
+                let mut urls = Vec::new();
+                while let Some(job) = scheduler.next().await? {
+                    urls.push(job.entry.url.clone());
+                }
+
+                Res::Ok(urls)
+            }
+        });
+
+        repo.lock()
+            .await
+            .add(base.child(String::from("https://example.com/about")))
+            .await?;
+        drop(job);
+
+        let urls = idle.await.context("Failed to join idle worker")??;
+
+        assert_eq!(urls, vec!["https://example.com/about"]);
+        assert!(scheduler.next().await?.is_none());
+
+        Ok(())
+    }
+}
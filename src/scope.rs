@@ -0,0 +1,269 @@
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use resext::ctx;
+use url::{Host, Url};
+
+use crate::error::{Res, ResExt};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleAction {
+    Include,
+    Exclude,
+}
+
+enum Pattern {
+    Regex(Regex),
+    Glob(GlobMatcher),
+}
+
+struct Rule {
+    action: RuleAction,
+    source: String,
+    pattern: Pattern,
+}
+
+/// Decides which URLs belong to the crawl: the domain and path prefix checks run first, then the
+/// `--include`/`--exclude` rules in the order they were given where the first match wins
+pub struct Scope {
+    domains: Vec<String>,
+    /// Parsed `domains`, a host without a port matches any port
+    hosts: Vec<(Host, Option<u16>)>,
+    include_subdomains: bool,
+    path_prefixes: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+impl Scope {
+    /// Builds the scope, `domains` are domains, IPv4 or IPv6 (bracketed or not) addresses with an
+    /// optional port, rule patterns starting with `re:` are regexes matched anywhere in the URL and
+    /// everything else is a glob matched against the whole URL
+    pub fn new(
+        domains: Vec<String>,
+        include_subdomains: bool,
+        path_prefixes: Vec<String>,
+        rules: &[(RuleAction, String)],
+    ) -> Res<Self> {
+        let rules = rules
+            .iter()
+            .map(|(action, source)| {
+                let pattern = match source.strip_prefix("re:") {
+                    Some(re) => Pattern::Regex(
+                        Regex::new(re)
+                            .map_err(Box::new)
+                            .context(ctx!("Invalid regex in scope rule: {}", source))?,
+                    ),
+                    None => Pattern::Glob(
+                        Glob::new(source)
+                            .map_err(Box::new)
+                            .context(ctx!("Invalid glob in scope rule: {}", source))?
+                            .compile_matcher(),
+                    ),
+                };
+
+                Ok(Rule {
+                    action: *action,
+                    source: source.clone(),
+                    pattern,
+                })
+            })
+            .collect::<Res<Vec<Rule>>>()?;
+
+        let hosts = domains
+            .iter()
+            .map(|domain| parse_host(domain.trim().trim_start_matches('.')))
+            .collect::<Res<Vec<(Host, Option<u16>)>>>()?;
+
+        Ok(Self {
+            domains,
+            hosts,
+            include_subdomains,
+            path_prefixes,
+            rules,
+        })
+    }
+
+    /// Returns the rule that rejected the URL, if any
+    pub fn check(&self, url: &Url) -> Result<(), String> {
+        let Some(host) = url.host() else {
+            return Err(String::from("URL has no host"));
+        };
+        let port = url.port_or_known_default();
+
+        let is_allowed_domain = self.hosts.iter().any(|(allowed, allowed_port)| {
+            let is_allowed_host = match (&host, allowed) {
+                (Host::Domain(domain), Host::Domain(allowed)) => {
+                    let domain = domain.to_ascii_lowercase();
+
+                    domain == *allowed
+                        || (self.include_subdomains && domain.ends_with(&format!(".{}", allowed)))
+                }
+                (host, allowed) => host.to_owned() == *allowed,
+            };
+
+            is_allowed_host && (allowed_port.is_none() || *allowed_port == port)
+        });
+
+        if !is_allowed_domain {
+            return Err(format!(
+                "--allow-domain {}{}",
+                self.domains.join(","),
+                if self.include_subdomains {
+                    " --include-subdomains"
+                } else {
+                    ""
+                }
+            ));
+        }
+
+        if !self.path_prefixes.is_empty()
+            && !self
+                .path_prefixes
+                .iter()
+                .any(|prefix| url.path().starts_with(prefix.as_str()))
+        {
+            return Err(format!("--path-prefix {}", self.path_prefixes.join(",")));
+        }
+
+        let mut target = url.clone();
+        target.set_fragment(None);
+        let target = target.as_str();
+
+        let matched = self.rules.iter().find(|rule| match &rule.pattern {
+            Pattern::Regex(re) => re.is_match(target),
+            Pattern::Glob(glob) => glob.is_match(target),
+        });
+
+        match matched {
+            Some(rule) if rule.action == RuleAction::Exclude => {
+                Err(format!("--exclude '{}'", rule.source))
+            }
+            Some(_) => Ok(()),
+
+            // Include rules turn the scope into an allow-list
+            None if self
+                .rules
+                .iter()
+                .any(|rule| rule.action == RuleAction::Include) =>
+            {
+                Err(String::from("no --include rule matched"))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses 'host[:port]', where host can also be an IPv6 address without brackets
+fn parse_host(s: &str) -> Res<(Host, Option<u16>)> {
+    let (host, port) = match s.rsplit_once(':') {
+        Some((host, port)) if host.ends_with(']') || (!host.is_empty() && !host.contains(':')) => {
+            (host, Some(port))
+        }
+        _ => (s, None),
+    };
+
+    let host = if host.contains(':') && !host.starts_with('[') {
+        Host::parse(&format!("[{}]", host))
+    } else {
+        Host::parse(host)
+    }
+    .context(ctx!("Failed to parse allowed domain: {}", s))?;
+
+    let port = match port {
+        Some(port) => Some(
+            port.parse::<u16>()
+                .map_err(|_| url::ParseError::InvalidPort)
+                .context(ctx!("Failed to parse port of allowed domain: {}", s))?,
+        ),
+        None => None,
+    };
+
+    Ok((host, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::{
+        error::{Res, ResExt},
+        scope::{RuleAction, Scope},
+    };
+
+    #[test]
+    fn test_scope_rules() -> Res<()> {
+        let scope = Scope::new(
+            vec![String::from("example.com")],
+            true,
+            vec![String::from("/docs"), String::from("/blog")],
+            &[
+                (RuleAction::Exclude, String::from("*.pdf")),
+                (RuleAction::Include, String::from("re:/docs/rust-[a-z]+")),
+                (RuleAction::Include, String::from("*/blog/*")),
+            ],
+        )?;
+
+        let check = |url: &str| -> Res<Result<(), String>> {
+            Ok(scope.check(&Url::parse(url).context("Failed to parse URL")?))
+        };
+
+        assert_eq!(check("https://example.com/docs/rust-async#intro")?, Ok(()));
+        assert_eq!(check("https://api.example.com/blog/post")?, Ok(()));
+        assert_eq!(
+            check("https://example.com/docs/rust-async.pdf")?,
+            Err(String::from("--exclude '*.pdf'"))
+        );
+        assert_eq!(
+            check("https://example.com/docs/python")?,
+            Err(String::from("no --include rule matched"))
+        );
+        assert_eq!(
+            check("https://example.com/about")?,
+            Err(String::from("--path-prefix /docs,/blog"))
+        );
+        assert_eq!(
+            check("https://notexample.com/docs/rust-async")?,
+            Err(String::from(
+                "--allow-domain example.com --include-subdomains"
+            ))
+        );
+        assert_eq!(
+            check("http://127.0.0.1/docs/rust-async")?,
+            Err(String::from(
+                "--allow-domain example.com --include-subdomains"
+            ))
+        );
+        assert_eq!(
+            check("file:///docs/rust-async")?,
+            Err(String::from("URL has no host"))
+        );
+
+        let local = Scope::new(
+            vec![
+                String::from("127.0.0.1:8080"),
+                String::from("::1"),
+                String::from("LocalHost:443"),
+            ],
+            true,
+            Vec::new(),
+            &[],
+        )?;
+
+        let check = |url: &str| -> Res<bool> {
+            Ok(local
+                .check(&Url::parse(url).context("Failed to parse URL")?)
+                .is_ok())
+        };
+
+        assert!(check("http://127.0.0.1:8080/docs")?);
+        assert!(!check("http://127.0.0.1:9090/docs")?);
+        assert!(!check("http://127.0.0.2:8080/docs")?);
+        assert!(check("http://[::1]:3000/")?);
+        assert!(check("https://localhost/")?);
+        assert!(!check("http://localhost:8080/")?);
+        assert!(check("https://api.localhost:443/")?);
+
+        assert!(Scope::new(vec![String::from("localhost:http")], false, Vec::new(), &[]).is_err());
+
+        Ok(())
+    }
+}
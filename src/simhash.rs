@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+/// Pages with fewer words aren't fingerprinted, their SimHash is too unstable to compare
+const MIN_WORDS: usize = 20;
+
+/// 64-bit SimHash of the text's words (lowercased, punctuation trimmed) weighted by how often
+/// they occur, similar texts get fingerprints that differ in few bits
+pub fn simhash(text: &str) -> Option<u64> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+
+    let mut weights = [0i32; 64];
+
+    for word in &words {
+        let hash = hash_word(word);
+
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if (hash >> bit) & 1 == 1 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    Some(
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0)
+            .fold(0u64, |fingerprint, (bit, _)| fingerprint | (1 << bit)),
+    )
+}
+
+/// FNV-1a followed by a murmur3 finalizer, std's hasher isn't guaranteed to be stable across
+/// releases
+fn hash_word(word: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for byte in word.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Fingerprints of the pages written so far, used to flag near-duplicate pages
+///
+/// Fingerprints are split into `max_distance + 1` blocks of bits, two fingerprints that differ in
+/// at most `max_distance` bits have at least one identical block, so only the pages sharing a
+/// block with the fingerprint are compared
+pub struct NearDuplicates {
+    max_distance: u32,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    pages: Vec<(u64, String)>,
+    /// Per block, the indices into `pages` of the fingerprints with each value of the block
+    bands: Vec<HashMap<u64, Vec<usize>>>,
+}
+
+impl NearDuplicates {
+    /// Pages whose fingerprints differ in at most `max_distance` bits are near-duplicates (capped
+    /// at 63, fingerprints are 64 bits)
+    pub fn new(max_distance: u32) -> Self {
+        let max_distance = max_distance.min(63);
+
+        Self {
+            max_distance,
+            seen: Mutex::new(Seen {
+                pages: Vec::new(),
+                bands: vec![HashMap::new(); max_distance as usize + 1],
+            }),
+        }
+    }
+
+    /// Returns the URL of the first seen page the fingerprint is close to, otherwise remembers
+    /// the page so later ones are compared against it
+    pub async fn check(&self, url: &str, fingerprint: u64) -> Option<String> {
+        let mut seen = self.seen.lock().await;
+        let Seen { pages, bands } = &mut *seen;
+        let blocks = bands.len();
+
+        let original = bands
+            .iter()
+            .enumerate()
+            .filter_map(|(i, band)| band.get(&block(fingerprint, i, blocks)))
+            .flatten()
+            .filter(|&&page| (pages[page].0 ^ fingerprint).count_ones() <= self.max_distance)
+            .min()
+            .map(|&page| pages[page].1.clone());
+
+        if original.is_none() {
+            for (i, band) in bands.iter_mut().enumerate() {
+                band.entry(block(fingerprint, i, blocks))
+                    .or_default()
+                    .push(pages.len());
+            }
+            pages.push((fingerprint, url.to_owned()));
+        }
+
+        original
+    }
+}
+
+/// Value of the `i`th of `blocks` (at most 64) evenly sized blocks of the fingerprint's bits
+fn block(fingerprint: u64, i: usize, blocks: usize) -> u64 {
+    let (start, end) = (i * 64 / blocks, (i + 1) * 64 / blocks);
+    let mask = u64::MAX >> (64 - (end - start));
+
+    (fingerprint >> start) & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simhash::{NearDuplicates, simhash};
+
+    #[tokio::test]
+    async fn test_near_duplicates() {
+        let listing = |page: u32| {
+            format!(
+                "Rust async guide. Tokio is an asynchronous runtime for the Rust programming \
+                 language. It provides the building blocks needed for writing network \
+                 applications, with the flexibility to target a wide range of systems, from \
+                 large servers with dozens of cores to small embedded devices. At a high level, \
+                 Tokio provides a few major components: a multi-threaded runtime for executing \
+                 asynchronous code, an asynchronous version of the standard library and a large \
+                 ecosystem of libraries. Tokio is fast, reliable and easy to use, and its \
+                 scheduler keeps the overhead of spawning tasks low. Page {} of 12.",
+                page
+            )
+        };
+
+        let first = simhash(&listing(1)).unwrap_or_default();
+        let second = simhash(&listing(2)).unwrap_or_default();
+        let other = simhash(
+            "Python packaging has changed a lot over the years: setup.py gave way to \
+             pyproject.toml, build backends became pluggable and lock files are now common \
+             in applications that need reproducible installs across machines and CI.",
+        )
+        .unwrap_or_default();
+
+        assert!((first ^ second).count_ones() <= 6);
+        assert!((first ^ other).count_ones() > 10);
+        assert_eq!(simhash("Too short to fingerprint"), None);
+
+        let near_duplicates = NearDuplicates::new(6);
+
+        assert_eq!(
+            near_duplicates.check("/tags/rust?page=1", first).await,
+            None
+        );
+        assert_eq!(
+            near_duplicates.check("/tags/rust?page=2", second).await,
+            Some(String::from("/tags/rust?page=1"))
+        );
+        assert_eq!(near_duplicates.check("/python", other).await, None);
+
+        // One bit off in 6 of the 7 blocks, only the last block is still the same
+        let spread = (0..6).fold(first, |fingerprint, i| fingerprint ^ (1 << (i * 64 / 7)));
+
+        let near_duplicates = NearDuplicates::new(6);
+        near_duplicates.check("/a", first).await;
+
+        assert_eq!(
+            near_duplicates.check("/b", spread).await,
+            Some(String::from("/a"))
+        );
+        assert_eq!(near_duplicates.check("/c", spread ^ (1 << 63)).await, None);
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io::Read,
+};
+
+use flate2::read::GzDecoder;
+use quick_xml::{Reader, escape::resolve_predefined_entity, events::Event};
+use resext::ctx;
+use url::Url;
+
+use crate::{
+    crawler::CrawnClient,
+    error::{Log, Res, ResExt},
+    match_option,
+    repo::FrontierEntry,
+};
+
+/// Sitemap indexes can point to other indexes, so discovery stops after this many sitemaps
+const MAX_SITEMAPS: usize = 64;
+
+/// Size limit for a single sitemap from the sitemaps protocol (50 MiB), applied to the body as
+/// received and again after decompressing it
+const MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Sitemap {
+    /// `<sitemap><loc>` entries of a sitemap index
+    pub sitemaps: Vec<String>,
+    /// `<url>` entries of a URL set
+    pub urls: Vec<FrontierEntry>,
+}
+
+/// Collects URLs from the `Sitemap:` lines of robots.txt (or `/sitemap.xml` if there are none),
+/// following sitemap indexes
+pub async fn discover_sitemap_urls(
+    client: &CrawnClient,
+    base: &Url,
+    robots_sitemaps: &[String],
+) -> Res<Vec<FrontierEntry>> {
+    let mut queue: VecDeque<String> = robots_sitemaps.iter().cloned().collect();
+
+    if queue.is_empty() {
+        let default = base
+            .join("/sitemap.xml")
+            .context(ctx!("Failed to resolve sitemap URL for: {}", base))?;
+
+        queue.push_back(default.to_string());
+    }
+
+    let mut seen = HashSet::new();
+    let mut urls = Vec::new();
+
+    while let Some(sitemap_url) = queue.pop_front() {
+        if seen.len() >= MAX_SITEMAPS {
+            format!(
+                "Stopped sitemap discovery after {} sitemaps, skipping: {}",
+                MAX_SITEMAPS, &sitemap_url
+            )
+            .log()
+            .await?;
+
+            break;
+        }
+
+        if !seen.insert(sitemap_url.clone()) {
+            continue;
+        }
+
+        let sitemap = match_option!(fetch_sitemap(client, &sitemap_url).await.log().await?);
+
+        if crate::ARGS.verbose {
+            format!(
+                "Fetched sitemap from URL: {} ({} URLs, {} nested sitemaps)",
+                &sitemap_url,
+                sitemap.urls.len(),
+                sitemap.sitemaps.len()
+            )
+            .log()
+            .await?;
+        }
+
+        queue.extend(sitemap.sitemaps);
+        urls.extend(sitemap.urls);
+    }
+
+    Ok(urls)
+}
+
+async fn fetch_sitemap(client: &CrawnClient, url: &str) -> Res<Sitemap> {
+    let bytes = client
+        .get(url, MAX_SITEMAP_SIZE)
+        .await
+        .context(ctx!("Failed to fetch sitemap from URL: {}", url))?;
+
+    let url = url.to_owned();
+
+    tokio::task::spawn_blocking(move || {
+        // Gzipped sitemaps are detected by their magic bytes rather than the `.gz` extension
+        // since servers often send them without `Content-Encoding`
+        let content = if bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut buf = Vec::with_capacity(bytes.len() * 4);
+
+            GzDecoder::new(&bytes[..])
+                .take(MAX_SITEMAP_SIZE)
+                .read_to_end(&mut buf)
+                .context(ctx!(
+                    "Failed to decompress gzipped sitemap from URL: {}",
+                    &url
+                ))?;
+
+            String::from_utf8_lossy(&buf).into_owned()
+        } else {
+            String::from_utf8_lossy(&bytes).into_owned()
+        };
+
+        parse_sitemap(&content).context(ctx!("Failed to parse sitemap from URL: {}", &url))
+    })
+    .await
+    .context("Failed to parse sitemap concurrently")?
+}
+
+/// Parses a sitemap URL set or sitemap index, ignoring namespace prefixes and unknown tags
+pub fn parse_sitemap(content: &str) -> Res<Sitemap> {
+    let mut reader = Reader::from_str(content);
+    let mut sitemap = Sitemap::default();
+    let mut entry = FrontierEntry::default();
+    let mut field: Option<String> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader
+            .read_event()
+            .map_err(Box::new)
+            .context("Failed to read XML event from sitemap")?
+        {
+            Event::Start(tag) => {
+                let name = tag.local_name().as_ref().to_lowercase();
+
+                match name.as_str() {
+                    "url" | "sitemap" => entry = FrontierEntry::default(),
+                    "loc" | "lastmod" | "priority" => {
+                        text.clear();
+                        field = Some(name);
+                    }
+                    _ => {}
+                }
+            }
+
+            Event::Text(t) if field.is_some() => text.push_str(&t.xml10_content()),
+
+            Event::CData(t) if field.is_some() => text.push_str(&t),
+
+            Event::GeneralRef(r) if field.is_some() => {
+                if let Some(ch) = r
+                    .resolve_char_ref()
+                    .map_err(Box::new)
+                    .context("Failed to resolve character reference in sitemap")?
+                {
+                    text.push(ch);
+                } else if let Some(resolved) = resolve_predefined_entity(&r) {
+                    text.push_str(resolved);
+                }
+            }
+
+            Event::End(tag) => {
+                let name = tag.local_name().as_ref().to_lowercase();
+                let value = text.trim();
+
+                match name.as_str() {
+                    "loc" => entry.url = value.to_owned(),
+                    "lastmod" => entry.lastmod = Some(value.to_owned()).filter(|v| !v.is_empty()),
+                    "priority" => entry.priority = value.parse().ok(),
+
+                    "url" if !entry.url.is_empty() => sitemap.urls.push(std::mem::take(&mut entry)),
+                    "sitemap" if !entry.url.is_empty() => {
+                        sitemap.sitemaps.push(std::mem::take(&mut entry.url))
+                    }
+                    _ => {}
+                }
+
+                if field.as_deref() == Some(name.as_str()) {
+                    field = None;
+                }
+            }
+
+            Event::Eof => break,
+
+            _ => {}
+        }
+    }
+
+    Ok(sitemap)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Res, repo::FrontierEntry, sitemap::parse_sitemap};
+
+    #[test]
+    fn test_parse_sitemap() -> Res<()> {
+        let urlset = parse_sitemap(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc>https://example.com/search?q=rust&amp;page=2</loc>
+    <lastmod>2026-01-01</lastmod>
+    <priority>0.8</priority>
+  </url>
+  <url><loc><![CDATA[https://example.com/about]]></loc></url>
+</urlset>"#,
+        )?;
+
+        assert_eq!(
+            urlset.urls,
+            vec![
+                FrontierEntry {
+                    url: String::from("https://example.com/search?q=rust&page=2"),
+                    lastmod: Some(String::from("2026-01-01")),
+                    priority: Some(0.8),
+                    ..Default::default()
+                },
+                String::from("https://example.com/about").into(),
+            ]
+        );
+
+        let index = parse_sitemap(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<sm:sitemapindex xmlns:sm="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sm:sitemap><sm:loc>https://example.com/sitemap-docs.xml.gz</sm:loc></sm:sitemap>
+</sm:sitemapindex>"#,
+        )?;
+
+        assert_eq!(
+            index.sitemaps,
+            vec!["https://example.com/sitemap-docs.xml.gz"]
+        );
+        assert!(index.urls.is_empty());
+
+        Ok(())
+    }
+}
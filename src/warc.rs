@@ -0,0 +1,462 @@
+use resext::ctx;
+use std::{
+    io::Write,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use flate2::{Compression, write::GzEncoder};
+use reqwest::{
+    StatusCode, Version,
+    header::{HeaderMap, TRANSFER_ENCODING},
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{Mutex, OnceCell},
+};
+use url::Url;
+
+use crate::{
+    crawler::DEFAULT_USER_AGENT,
+    error::{Res, ResExt, UTC_TIMESTAMP_FORMAT},
+    output::numbered_path,
+};
+
+/// An HTTP exchange to archive, the body is kept as received (still content-encoded)
+pub struct Exchange<'a> {
+    /// Final URL after redirects
+    pub url: &'a Url,
+    pub request_headers: HeaderMap,
+    pub version: Version,
+    pub status: StatusCode,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+    pub remote_addr: Option<SocketAddr>,
+}
+
+/// WARC file being written, every record is a separate gzip member so readers can seek to it
+struct WarcWriter {
+    path: PathBuf,
+    file: File,
+    /// Number of the current file, 0 is `path` itself and rolled over files get `-00001`, ...
+    index: u32,
+    size: u64,
+    max_size: u64,
+}
+
+impl WarcWriter {
+    /// Opens the first file that isn't full yet, existing files are appended to so a resumed
+    /// crawl keeps the records of the previous run
+    async fn open(path: PathBuf, max_size: u64) -> Res<Self> {
+        let mut index = 0;
+
+        while tokio::fs::metadata(numbered_path(&path, index))
+            .await
+            .is_ok_and(|meta| meta.len() >= max_size)
+        {
+            index += 1;
+        }
+
+        let (file, size) = open_file(&numbered_path(&path, index)).await?;
+
+        let mut writer = Self {
+            path,
+            file,
+            index,
+            size,
+            max_size,
+        };
+        writer.write_warcinfo().await?;
+
+        Ok(writer)
+    }
+
+    /// Appends the records, rolling over to the next file first if they don't fit in this one
+    async fn write(&mut self, records: &[u8]) -> Res<()> {
+        if self.size > 0 && self.size + records.len() as u64 > self.max_size {
+            self.file
+                .flush()
+                .await
+                .context("Failed to flush WARC file")?;
+
+            self.index += 1;
+            (self.file, self.size) = open_file(&numbered_path(&self.path, self.index)).await?;
+            self.write_warcinfo().await?;
+        }
+
+        self.append(records).await
+    }
+
+    async fn append(&mut self, records: &[u8]) -> Res<()> {
+        self.file.write_all(records).await.context(ctx!(
+            "Failed to write WARC file: {}",
+            numbered_path(&self.path, self.index).to_string_lossy()
+        ))?;
+        self.size += records.len() as u64;
+
+        Ok(())
+    }
+
+    /// Every file starts with a `warcinfo` record describing the crawl
+    async fn write_warcinfo(&mut self) -> Res<()> {
+        let args = &*crate::ARGS;
+        let path = numbered_path(&self.path, self.index);
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let info = format!(
+            "software: crawn/{}\r\n\
+             format: WARC File Format 1.1\r\n\
+             conformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n\
+             robots: {}\r\n\
+             http-header-user-agent: {}\r\n",
+            env!("CARGO_PKG_VERSION"),
+            if args.ignore_robots { "ignore" } else { "obey" },
+            args.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+        );
+
+        let record = record(
+            "warcinfo",
+            &record_id(),
+            &warc_date()?,
+            &[("WARC-Filename", &filename)],
+            "application/warc-fields",
+            info.as_bytes(),
+        )?;
+
+        self.append(&record).await
+    }
+}
+
+static WARC: OnceCell<Option<Mutex<WarcWriter>>> = OnceCell::const_new();
+
+/// The WARC writer, `None` without `--warc`
+async fn init_warc() -> Res<Option<&'static Mutex<WarcWriter>>> {
+    let writer = WARC
+        .get_or_try_init(async || -> Res<Option<Mutex<WarcWriter>>> {
+            let args = &*crate::ARGS;
+
+            match &args.warc {
+                Some(path) => Ok(Some(Mutex::new(
+                    WarcWriter::open(
+                        path.clone(),
+                        args.warc_max_size.unwrap_or(1024 * 1024 * 1024),
+                    )
+                    .await?,
+                ))),
+                None => Ok(None),
+            }
+        })
+        .await?;
+
+    Ok(writer.as_ref())
+}
+
+/// Writes the `request` and `response` records of the exchange, returns the response's record
+/// ID (`None` without `--warc`) so the page's `metadata` record can refer to it
+pub async fn write_exchange(exchange: Exchange<'_>) -> Res<Option<String>> {
+    let Some(writer) = init_warc().await? else {
+        return Ok(None);
+    };
+
+    let target = exchange.url.as_str();
+    let date = warc_date()?;
+    let response_id = record_id();
+
+    let response = {
+        let mut block = format!("{:?} {}\r\n", exchange.version, exchange.status).into_bytes();
+
+        // The body was already de-chunked, so the header would make readers de-chunk it again
+        for (name, value) in exchange.headers {
+            if name != TRANSFER_ENCODING {
+                block.extend_from_slice(&header_line(name.as_str(), value.as_bytes()));
+            }
+        }
+        block.extend_from_slice(b"\r\n");
+        block.extend_from_slice(exchange.body);
+
+        let payload_digest = digest(exchange.body);
+        let ip = exchange
+            .remote_addr
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+
+        let mut fields = vec![
+            ("WARC-Target-URI", target),
+            ("WARC-Payload-Digest", payload_digest.as_str()),
+        ];
+        if !ip.is_empty() {
+            fields.push(("WARC-IP-Address", ip.as_str()));
+        }
+
+        record(
+            "response",
+            &response_id,
+            &date,
+            &fields,
+            "application/http;msgtype=response",
+            &block,
+        )?
+    };
+
+    let request = {
+        let mut path = String::from(exchange.url.path());
+        if let Some(query) = exchange.url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        let host = match exchange.url.port() {
+            Some(port) => format!("{}:{}", exchange.url.host_str().unwrap_or_default(), port),
+            None => exchange.url.host_str().unwrap_or_default().to_owned(),
+        };
+
+        let mut block = format!("GET {} HTTP/1.1\r\n", path).into_bytes();
+        block.extend_from_slice(&header_line("host", host.as_bytes()));
+        for (name, value) in &exchange.request_headers {
+            block.extend_from_slice(&header_line(name.as_str(), value.as_bytes()));
+        }
+        block.extend_from_slice(b"\r\n");
+
+        record(
+            "request",
+            &record_id(),
+            &date,
+            &[
+                ("WARC-Target-URI", target),
+                ("WARC-Concurrent-To", &response_id),
+            ],
+            "application/http;msgtype=request",
+            &block,
+        )?
+    };
+
+    // Written together so a rollover can't split the exchange across files
+    writer
+        .lock()
+        .await
+        .write(&[request, response].concat())
+        .await?;
+
+    Ok(Some(response_id))
+}
+
+/// Writes a `metadata` record with the page's outlinks (if it has any), `concurrent_to` is the ID of the page's
+/// `response` record
+pub async fn write_metadata(url: &str, concurrent_to: &str, outlinks: &[String]) -> Res<()> {
+    let Some(writer) = init_warc().await? else {
+        return Ok(());
+    };
+
+    if outlinks.is_empty() {
+        return Ok(());
+    }
+
+    let fields: String = outlinks
+        .iter()
+        .map(|link| format!("outlink: {}\r\n", link))
+        .collect();
+
+    let record = record(
+        "metadata",
+        &record_id(),
+        &warc_date()?,
+        &[
+            ("WARC-Target-URI", url),
+            ("WARC-Concurrent-To", concurrent_to),
+        ],
+        "application/warc-fields",
+        fields.as_bytes(),
+    )?;
+
+    writer.lock().await.write(&record).await
+}
+
+/// Flushes the WARC file, a no-op without `--warc`
+pub async fn flush_warc() -> Res<()> {
+    match init_warc().await? {
+        Some(writer) => writer
+            .lock()
+            .await
+            .file
+            .flush()
+            .await
+            .context("Failed to flush WARC file"),
+        None => Ok(()),
+    }
+}
+
+/// Builds a gzip-compressed WARC 1.1 record
+fn record(
+    kind: &str,
+    id: &str,
+    date: &str,
+    fields: &[(&str, &str)],
+    content_type: &str,
+    block: &[u8],
+) -> Res<Vec<u8>> {
+    let mut record = format!(
+        "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+        kind, id, date
+    );
+
+    for (name, value) in fields {
+        record.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    record.push_str(&format!(
+        "WARC-Block-Digest: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        digest(block),
+        content_type,
+        block.len()
+    ));
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(record.as_bytes())
+        .and_then(|_| encoder.write_all(block))
+        .and_then(|_| encoder.write_all(b"\r\n\r\n"))
+        .context(ctx!("Failed to compress WARC {} record", kind))?;
+
+    encoder
+        .finish()
+        .context(ctx!("Failed to compress WARC {} record", kind))
+}
+
+fn header_line(name: &str, value: &[u8]) -> Vec<u8> {
+    [name.as_bytes(), b": ", value, b"\r\n"].concat()
+}
+
+/// `<urn:uuid:...>` with a random (version 4) UUID
+fn record_id() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    format!(
+        "<urn:uuid:{}-{}-{}-{}-{}>",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn warc_date() -> Res<String> {
+    time::OffsetDateTime::now_utc()
+        .format(&UTC_TIMESTAMP_FORMAT)
+        .map_err(|_| String::from("Format Failure"))
+        .context("Failed to format WARC date")
+}
+
+/// SHA-1 digest in the `sha1:<base32>` form used by WARC readers
+fn digest(bytes: &[u8]) -> String {
+    format!(
+        "sha1:{}",
+        base32(&sha1_smol::Sha1::from(bytes).digest().bytes())
+    )
+}
+
+/// RFC 4648 base32 without padding
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+
+        buffer &= (1 << bits) - 1;
+    }
+
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+
+    out
+}
+
+async fn open_file(path: &Path) -> Res<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .context(ctx!("Failed to open WARC file: {}", path.to_string_lossy()))?;
+
+    let size = file
+        .metadata()
+        .await
+        .context(ctx!("Failed to read WARC file: {}", path.to_string_lossy()))?
+        .len();
+
+    Ok((file, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use crate::{
+        error::{Res, ResExt},
+        warc::{base32, digest, record},
+    };
+
+    #[test]
+    fn test_warc_record() -> Res<()> {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(digest(b""), "sha1:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ");
+
+        let block = b"HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\r\n<html></html>";
+        let gzipped = record(
+            "response",
+            "<urn:uuid:00000000-0000-4000-8000-000000000000>",
+            "2026-01-02T03:04:05Z",
+            &[("WARC-Target-URI", "https://example.com/")],
+            "application/http;msgtype=response",
+            block,
+        )?;
+
+        let mut decoded = String::new();
+        GzDecoder::new(&gzipped[..])
+            .read_to_string(&mut decoded)
+            .context("Failed to decompress WARC record")?;
+
+        assert_eq!(
+            decoded,
+            format!(
+                "WARC/1.1\r\n\
+                 WARC-Type: response\r\n\
+                 WARC-Record-ID: <urn:uuid:00000000-0000-4000-8000-000000000000>\r\n\
+                 WARC-Date: 2026-01-02T03:04:05Z\r\n\
+                 WARC-Target-URI: https://example.com/\r\n\
+                 WARC-Block-Digest: {}\r\n\
+                 Content-Type: application/http;msgtype=response\r\n\
+                 Content-Length: {}\r\n\
+                 \r\n\
+                 {}\r\n\r\n",
+                digest(block),
+                block.len(),
+                String::from_utf8_lossy(block)
+            )
+        );
+
+        Ok(())
+    }
+}